@@ -0,0 +1,280 @@
+use bevy::prelude::*;
+use bevy_light_2d::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    gamestate::GameState,
+    maze::{HeadCall, Maze, MazeFloorChanged, MazeHeadCall, MazeNode, MazeRebuilt},
+    maze_specs::MazeColor,
+    player::{player_alive, ManaState, Player},
+};
+
+pub struct AbilityPlugin;
+
+impl Plugin for AbilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ScanTimer(Timer::from_seconds(3., TimerMode::Once)));
+        app.add_systems(
+            Update,
+            start_scan
+                .run_if(in_state(GameState::InGame))
+                .run_if(player_alive),
+        );
+        app.add_systems(Update, end_scan.run_if(in_state(GameState::Scanning)));
+        app.add_systems(OnEnter(GameState::Scanning), light_up_maze);
+        app.add_systems(OnExit(GameState::Scanning), darken_maze);
+        app.insert_resource(Lamps {
+            available: LAMP_COUNT,
+            placed: Vec::new(),
+        });
+        app.add_systems(Update, spawn_lamps);
+        app.add_systems(Startup, spawn_head_marker);
+        app.add_systems(Update, move_head_marker);
+        app.add_systems(
+            Update,
+            call_maze_head
+                .run_if(in_state(GameState::InGame))
+                .run_if(player_alive),
+        );
+        app.add_systems(
+            Update,
+            toggle_lamp
+                .before(spawn_lamps)
+                .run_if(in_state(GameState::InGame))
+                .run_if(player_alive),
+        );
+    }
+}
+
+const SCAN_COST: f32 = 40.0;
+const LAMP_COST: f32 = 15.0;
+const LAMP_COUNT: usize = 5;
+const CALL_HEAD_COST: f32 = 30.0;
+/// Steps the head takes toward the player before giving up
+const CALL_HEAD_STEPS: usize = 60;
+
+/// How long a scan shows the whole maze
+#[derive(Resource)]
+struct ScanTimer(Timer);
+
+/// Lamps the player still carries and the cells the others were left in
+#[derive(Resource)]
+pub struct Lamps {
+    pub available: usize,
+    placed: Vec<PlacedLamp>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PlacedLamp {
+    floor: usize,
+    cell: usize,
+}
+
+/// Light of a placed lamp, a child of the node of its cell
+#[derive(Component)]
+struct Lamp;
+
+/// Shows where the root of the current floor is
+#[derive(Component)]
+struct HeadMarker;
+
+// Press Q to see the entire maze for a moment
+fn start_scan(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut mana_state: ResMut<ManaState>,
+    mut timer: ResMut<ScanTimer>,
+    mut player_query: Query<&mut Velocity, With<Player>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyQ) || mana_state.percentage < SCAN_COST {
+        return;
+    }
+
+    mana_state.percentage -= SCAN_COST;
+    mana_state.recovery_timer.reset();
+    timer.0.reset();
+
+    // the player stands still while the camera is away
+    for mut velocity in player_query.iter_mut() {
+        velocity.linvel = Vec2::ZERO;
+    }
+    next_state.set(GameState::Scanning);
+}
+
+fn end_scan(
+    time: Res<Time>,
+    mut timer: ResMut<ScanTimer>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if timer.0.tick(time.delta()).just_finished() {
+        next_state.set(GameState::InGame);
+    }
+}
+
+fn light_up_maze(mut commands: Commands, camera_query: Query<Entity, With<Camera2d>>) {
+    for camera in camera_query.iter() {
+        commands.entity(camera).insert(AmbientLight2d {
+            brightness: 1.,
+            ..default()
+        });
+    }
+}
+
+fn darken_maze(mut commands: Commands, camera_query: Query<Entity, With<Camera2d>>) {
+    for camera in camera_query.iter() {
+        commands.entity(camera).remove::<AmbientLight2d>();
+    }
+}
+
+// Press R to put a lamp down in the current cell, or to pick up the one already there
+fn toggle_lamp(
+    keys: Res<ButtonInput<KeyCode>>,
+    player_query: Query<&Transform, With<Player>>,
+    maze: Res<Maze>,
+    mut lamps: ResMut<Lamps>,
+    mut mana_state: ResMut<ManaState>,
+) {
+    if !keys.just_pressed(KeyCode::KeyR) {
+        return;
+    }
+    for transform in player_query.iter() {
+        let Some(cell) = maze.cell_at(transform.translation.truncate()) else {
+            continue;
+        };
+        let lamp = PlacedLamp {
+            floor: maze.current_floor,
+            cell,
+        };
+
+        if let Some(index) = lamps.placed.iter().position(|placed| *placed == lamp) {
+            lamps.placed.swap_remove(index);
+            lamps.available += 1;
+        } else if lamps.available > 0 && mana_state.percentage >= LAMP_COST {
+            lamps.placed.push(lamp);
+            lamps.available -= 1;
+            mana_state.percentage -= LAMP_COST;
+            mana_state.recovery_timer.reset();
+        }
+    }
+}
+
+// Only the lamps of the current floor exist in the world, and a new maze hands all of them back
+#[allow(clippy::too_many_arguments)]
+fn spawn_lamps(
+    mut commands: Commands,
+    mut rebuilt: EventReader<MazeRebuilt>,
+    mut floor_changed: EventReader<MazeFloorChanged>,
+    node_query: Query<(Entity, &MazeNode)>,
+    lamp_query: Query<Entity, With<Lamp>>,
+    maze: Res<Maze>,
+    mut lamps: ResMut<Lamps>,
+    color: Res<MazeColor>,
+) {
+    if rebuilt.is_empty() && floor_changed.is_empty() && !lamps.is_changed() {
+        return;
+    }
+    if !rebuilt.is_empty() {
+        lamps.available = LAMP_COUNT;
+        lamps.placed.clear();
+    }
+    rebuilt.clear();
+    floor_changed.clear();
+
+    for entity in lamp_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for (entity, node) in node_query.iter() {
+        let placed = lamps
+            .placed
+            .iter()
+            .any(|lamp| lamp.floor == maze.current_floor && lamp.cell == node.index);
+        if !placed {
+            continue;
+        }
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn((
+                ShapeBundle {
+                    path: GeometryBuilder::build_as(&shapes::Circle {
+                        radius: maze.path_thickness * 0.15,
+                        ..default()
+                    }),
+                    transform: Transform::from_translation(Vec3::new(0., 0., -4.)),
+                    ..default()
+                },
+                Fill::color(color.light_color),
+                PointLight2d {
+                    intensity: 5.0,
+                    radius: maze.view_distance,
+                    falloff: 5.,
+                    cast_shadows: true,
+                    color: color.light_color,
+                },
+                Lamp,
+            ));
+        });
+    }
+}
+
+// Press C to pull the head of the current floor toward the player, reshaping the maze around them
+fn call_maze_head(
+    keys: Res<ButtonInput<KeyCode>>,
+    player_query: Query<&Transform, With<Player>>,
+    maze: Res<Maze>,
+    mut head_call: ResMut<MazeHeadCall>,
+    mut mana_state: ResMut<ManaState>,
+) {
+    if !keys.just_pressed(KeyCode::KeyC)
+        || head_call.0.is_some()
+        || mana_state.percentage < CALL_HEAD_COST
+    {
+        return;
+    }
+    let Some(target) = player_query
+        .iter()
+        .find_map(|transform| maze.cell_at(transform.translation.truncate()))
+    else {
+        return;
+    };
+
+    head_call.0 = Some(HeadCall {
+        floor: maze.current_floor,
+        target,
+        steps_left: CALL_HEAD_STEPS,
+    });
+    mana_state.percentage -= CALL_HEAD_COST;
+    mana_state.recovery_timer.reset();
+}
+
+fn spawn_head_marker(mut commands: Commands, maze: Res<Maze>, color: Res<MazeColor>) {
+    let position = maze.cell_position(maze.graph().root());
+    commands.spawn((
+        ShapeBundle {
+            path: GeometryBuilder::build_as(&shapes::Circle {
+                radius: maze.path_thickness * 0.2,
+                ..default()
+            }),
+            transform: Transform::from_translation(position.extend(-4.)),
+            ..default()
+        },
+        Fill::color(color.head_color),
+        PointLight2d {
+            intensity: 2.0,
+            radius: maze.cell_size,
+            falloff: 5.,
+            cast_shadows: true,
+            color: color.head_color,
+        },
+        HeadMarker,
+    ));
+}
+
+fn move_head_marker(maze: Res<Maze>, mut marker_query: Query<&mut Transform, With<HeadMarker>>) {
+    if !maze.is_changed() {
+        return;
+    }
+    for mut transform in marker_query.iter_mut() {
+        let position = maze.cell_position(maze.graph().root());
+        transform.translation = position.extend(transform.translation.z);
+    }
+}
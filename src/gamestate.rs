@@ -15,6 +15,8 @@ pub enum GameState {
     InGame,
     Pauzed,
     Scanning,
+    Victory,
+    GameOver,
     #[default]
     MainMenu,
 }
@@ -38,6 +40,7 @@ fn toggle_pause(
             GameState::Pauzed => next_state.set(prev_state.0.clone().unwrap()),
             GameState::Scanning => next_state.set(GameState::Pauzed),
             GameState::InGame => next_state.set(GameState::Pauzed),
+            GameState::Victory | GameState::GameOver => {}
         }
     }
 }
@@ -0,0 +1,348 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::seq::IndexedRandom;
+
+use crate::{
+    gamestate::GameState,
+    maze::{Maze, MazeEdgeChanged, MazeFloorChanged, MazeRebuilt},
+    maze_graph::Direction,
+    npc::{closed_passages, facing, replan_route, reseed_npc_rng, walk_route, NpcRng},
+    player::{character_sprite, Player, PlayerAnimations, PlayerDamaged, PlayerState},
+    stealth::LightVisibility,
+};
+
+pub struct GuardPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for GuardPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            spawn_guards::<S>
+                .after(reseed_npc_rng)
+                .run_if(in_state(self.state.clone())),
+        );
+        app.add_systems(
+            Update,
+            (
+                spot_player,
+                plan_guards,
+                move_guards,
+                update_guard_animation,
+                update_vision_cones,
+                hit_player,
+            )
+                .chain()
+                .after(spawn_guards::<S>)
+                .run_if(in_state(self.state.clone())),
+        );
+        app.add_systems(OnEnter(GameState::GameOver), despawn_guards);
+    }
+}
+
+const GUARDS_PER_FLOOR: usize = 2;
+/// Fewest steps between the player and a freshly spawned guard, ignoring walls
+const GUARD_SPAWN_DISTANCE: usize = 10;
+/// Steps between the two ends of a patrol, along the tree
+const PATROL_LENGTH: usize = 8;
+/// Half the opening angle of a vision cone, in radians
+const VISION_HALF_ANGLE: f32 = 0.6;
+/// Vision range in cells
+const VISION_RANGE: f32 = 3.0;
+/// Share of its range a guard still sees a player standing in the dark
+const DARK_SIGHT: f32 = 0.3;
+/// Rays cast to outline a vision cone against the walls
+const VISION_RAYS: usize = 12;
+/// Seconds a guard keeps chasing after losing sight of the player
+const ALERT_DURATION: f32 = 3.0;
+
+/// Walks back and forth between two cells, chasing the player once they are spotted
+#[derive(Component)]
+pub struct Guard {
+    speed: f32,
+    alert_speed: f32,
+    /// Vision range in world units
+    range: f32,
+    patrol: (usize, usize),
+    /// Cells still to walk through, starting with the one the guard last reached
+    route: Vec<usize>,
+    heading: Vec2,
+    alert: GuardAlert,
+    state: PlayerState,
+    direction: Direction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GuardAlert {
+    Patrolling,
+    /// Chasing the player, calming down when the timer runs out
+    Alerted(f32),
+}
+
+/// Area a guard sees, a child of the guard cut off by the walls
+#[derive(Component)]
+struct VisionCone;
+
+// Like hunters, every floor has its own guards, patrolling away from the player
+#[allow(clippy::too_many_arguments)]
+fn spawn_guards<S: States>(
+    mut commands: Commands,
+    state: Res<State<S>>,
+    mut rebuilt: EventReader<MazeRebuilt>,
+    mut floor_changed: EventReader<MazeFloorChanged>,
+    guard_query: Query<Entity, With<Guard>>,
+    player_query: Query<&Transform, With<Player>>,
+    maze: Res<Maze>,
+    mut rng: ResMut<NpcRng>,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+) {
+    // mazes built in the menus get their guards once the game starts
+    let entered = state.is_changed() && guard_query.is_empty();
+    if rebuilt.is_empty() && floor_changed.is_empty() && !entered {
+        return;
+    }
+    let rebuilding = !rebuilt.is_empty();
+    rebuilt.clear();
+    floor_changed.clear();
+
+    for entity in guard_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    // a new maze moves the player back to the spawn later this frame
+    let player_cell = player_query
+        .iter()
+        .find_map(|transform| maze.cell_at(transform.translation.truncate()))
+        .filter(|_| !rebuilding)
+        .unwrap_or(maze.spawn);
+    let graph = maze.graph();
+    let player_distances = graph.distances(player_cell);
+    let starts: Vec<usize> = graph
+        .cells()
+        .filter(|cell| player_distances[*cell].is_some_and(|d| d >= GUARD_SPAWN_DISTANCE))
+        .collect();
+
+    for &start in starts.choose_multiple(&mut rng.0, GUARDS_PER_FLOOR) {
+        // the patrol ends as close to the wanted length as the region allows
+        let end = graph
+            .cells()
+            .filter_map(|cell| Some((cell, graph.tree_path(start, cell)?.len() - 1)))
+            .min_by_key(|(_, steps)| steps.abs_diff(PATROL_LENGTH))
+            .map_or(start, |(cell, _)| cell);
+
+        let (sprite, animations) = character_sprite(
+            "sprite/character/Prototype_Character.png",
+            &asset_server,
+            &mut texture_atlases,
+        );
+        commands
+            .spawn((
+                sprite,
+                animations,
+                Transform::from_translation(maze.cell_position(start).extend(0.)),
+                Visibility::default(),
+                Guard {
+                    speed: 80.0,
+                    alert_speed: 150.0,
+                    range: maze.cell_size * VISION_RANGE,
+                    patrol: (start, end),
+                    route: graph.tree_path(start, end).unwrap_or_default(),
+                    heading: Vec2::NEG_Y,
+                    alert: GuardAlert::Patrolling,
+                    state: PlayerState::Idle,
+                    direction: Direction::Down,
+                },
+            ))
+            .with_children(|parent| {
+                parent.spawn((
+                    ShapeBundle {
+                        transform: Transform::from_translation(Vec3::new(0., 0., -1.)),
+                        ..default()
+                    },
+                    Fill::color(Color::NONE),
+                    VisionCone,
+                ));
+            });
+    }
+}
+
+// A guard sees the player inside its cone, unless a wall is in between, and
+// only from up close when the player keeps to the dark
+fn spot_player(
+    mut guard_query: Query<(&mut Guard, &Transform)>,
+    player_query: Query<(&Transform, &LightVisibility), With<Player>>,
+    rapier_context: ReadRapierContext,
+    time: Res<Time>,
+) {
+    let Ok((player, visibility)) = player_query.get_single() else {
+        return;
+    };
+    let player_position = player.translation.truncate();
+    let sight = DARK_SIGHT + (1. - DARK_SIGHT) * visibility.0;
+
+    for (mut guard, transform) in guard_query.iter_mut() {
+        let position = transform.translation.truncate();
+        let to_player = player_position - position;
+        let distance = to_player.length();
+
+        let in_cone = distance <= guard.range * sight
+            && (distance == 0. || guard.heading.angle_to(to_player).abs() <= VISION_HALF_ANGLE);
+        let seen = in_cone
+            && rapier_context
+                .single()
+                .cast_ray(
+                    position,
+                    to_player.normalize_or_zero(),
+                    distance,
+                    true,
+                    QueryFilter::<'_>::exclude_dynamic().exclude_sensors(),
+                )
+                .is_none();
+
+        guard.alert = match guard.alert {
+            _ if seen => GuardAlert::Alerted(ALERT_DURATION),
+            GuardAlert::Alerted(left) if left > time.delta_secs() => {
+                GuardAlert::Alerted(left - time.delta_secs())
+            }
+            _ => GuardAlert::Patrolling,
+        };
+    }
+}
+
+// Patrols and chases both follow the tree, replanned when the origin-shift cuts them
+fn plan_guards(
+    mut changes: EventReader<MazeEdgeChanged>,
+    mut guard_query: Query<(&mut Guard, &mut Transform)>,
+    player_query: Query<&Transform, (With<Player>, Without<Guard>)>,
+    maze: Res<Maze>,
+) {
+    let closed = closed_passages(changes.read(), &maze);
+    let player_cell = player_query
+        .iter()
+        .find_map(|transform| maze.cell_at(transform.translation.truncate()));
+
+    for (mut guard, mut transform) in guard_query.iter_mut() {
+        let Some(cell) = maze.cell_at(transform.translation.truncate()) else {
+            continue;
+        };
+        let target = match (guard.alert, player_cell) {
+            (GuardAlert::Alerted(_), Some(player_cell)) => player_cell,
+            // turn around at the end of the patrol
+            _ if guard.route.len() <= 1 && cell == guard.patrol.1 => {
+                guard.patrol = (guard.patrol.1, guard.patrol.0);
+                guard.patrol.1
+            }
+            _ => guard.patrol.1,
+        };
+
+        replan_route(&mut guard.route, &mut transform, target, &closed, &maze);
+    }
+}
+
+fn move_guards(
+    mut guard_query: Query<(&mut Guard, &mut Transform)>,
+    maze: Res<Maze>,
+    time: Res<Time>,
+) {
+    for (mut guard, mut transform) in guard_query.iter_mut() {
+        let speed = match guard.alert {
+            GuardAlert::Patrolling => guard.speed,
+            GuardAlert::Alerted(_) => guard.alert_speed,
+        };
+        match walk_route(
+            &mut guard.route,
+            &mut transform,
+            speed * time.delta_secs(),
+            &maze,
+        ) {
+            Some(heading) => {
+                if heading != Vec2::ZERO {
+                    guard.heading = heading.normalize();
+                }
+                guard.direction = facing(heading);
+                guard.state = PlayerState::Walking;
+            }
+            None => guard.state = PlayerState::Idle,
+        }
+    }
+}
+
+fn update_guard_animation(mut query: Query<(&Guard, &mut PlayerAnimations, &mut Sprite)>) {
+    for (guard, mut animations, mut sprite) in query.iter_mut() {
+        animations.play(guard.state.clone(), guard.direction, &mut sprite);
+    }
+}
+
+// The walls that cast the shadows also stop the rays outlining the cones
+fn update_vision_cones(
+    guard_query: Query<(&Guard, &Transform, &Children)>,
+    mut cone_query: Query<(&mut Path, &mut Fill), With<VisionCone>>,
+    rapier_context: ReadRapierContext,
+) {
+    for (guard, transform, children) in guard_query.iter() {
+        let position = transform.translation.truncate();
+        let mut points = vec![Vec2::ZERO];
+
+        for ray in 0..=VISION_RAYS {
+            let angle =
+                -VISION_HALF_ANGLE + 2. * VISION_HALF_ANGLE * ray as f32 / VISION_RAYS as f32;
+            let direction = Vec2::from_angle(angle).rotate(guard.heading);
+            let length = rapier_context
+                .single()
+                .cast_ray(
+                    position,
+                    direction,
+                    guard.range,
+                    true,
+                    QueryFilter::<'_>::exclude_dynamic().exclude_sensors(),
+                )
+                .map_or(guard.range, |(_, distance)| distance);
+            points.push(direction * length);
+        }
+
+        let color = match guard.alert {
+            GuardAlert::Patrolling => Color::srgba(1.0, 1.0, 0.6, 0.15),
+            GuardAlert::Alerted(_) => Color::srgba(1.0, 0.2, 0.2, 0.25),
+        };
+        for &child in children.iter() {
+            if let Ok((mut path, mut fill)) = cone_query.get_mut(child) {
+                *path = GeometryBuilder::build_as(&shapes::Polygon {
+                    points: points.clone(),
+                    closed: true,
+                });
+                fill.color = color;
+            }
+        }
+    }
+}
+
+// An alerted guard reaching the player hurts them, like a hunter
+fn hit_player(
+    guard_query: Query<(&Guard, &Transform)>,
+    player_query: Query<&Transform, With<Player>>,
+    maze: Res<Maze>,
+    mut damaged: EventWriter<PlayerDamaged>,
+) {
+    for player in player_query.iter() {
+        let hit = guard_query.iter().any(|(guard, transform)| {
+            matches!(guard.alert, GuardAlert::Alerted(_))
+                && transform
+                    .translation
+                    .truncate()
+                    .distance(player.translation.truncate())
+                    < maze.cell_size * 0.3
+        });
+        if hit {
+            damaged.send(PlayerDamaged { amount: 1 });
+        }
+    }
+}
+
+fn despawn_guards(mut commands: Commands, guard_query: Query<Entity, With<Guard>>) {
+    for entity in guard_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,6 +1,12 @@
 use bevy::prelude::*;
 
-use crate::player::ManaState;
+use crate::{
+    abilities::Lamps,
+    maze::{Maze, MazeFloorChanged, MazeRebuilt},
+    maze_specs::MazeSeed,
+    objectives::LightCoverage,
+    player::{Health, ManaState, Player},
+};
 
 pub struct HudPlugin<S: States> {
     pub state: S,
@@ -9,13 +15,23 @@ pub struct HudPlugin<S: States> {
 impl<S: States> Plugin for HudPlugin<S> {
     fn build(&self, app: &mut App) {
         app.insert_resource(ScoreTimer(Timer::from_seconds(1., TimerMode::Repeating)));
+        app.init_resource::<Score>();
+        app.add_systems(Update, (reset_score, update_floor_text));
         app.add_systems(OnEnter(self.state.clone()), setup_hud);
         app.add_systems(Update, update_hud.run_if(in_state(self.state.clone())));
     }
 }
 
+/// Seconds spent in the current maze
+#[derive(Resource, Default)]
+pub struct Score(pub f32);
+
+/// Root of the HUD, which stays up while the player is scanning or paused
+#[derive(Component)]
+struct Hud;
+
 #[derive(Component)]
-struct ScoreValue(f32);
+struct ScoreValue;
 
 #[derive(Resource)]
 struct ScoreTimer(Timer);
@@ -23,22 +39,61 @@ struct ScoreTimer(Timer);
 #[derive(Component)]
 struct ManaValue;
 
-fn setup_hud(mut commands: Commands, asset_server: Res<AssetServer>, mana_state: Res<ManaState>) {
+#[derive(Component)]
+struct SeedValue;
+
+#[derive(Component)]
+struct FloorValue;
+
+#[derive(Component)]
+struct CoverageValue;
+
+#[derive(Component)]
+struct LampValue;
+
+#[derive(Component)]
+struct HealthValue;
+
+#[allow(clippy::too_many_arguments)]
+fn setup_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mana_state: Res<ManaState>,
+    seed: Res<MazeSeed>,
+    maze: Res<Maze>,
+    score: Res<Score>,
+    coverage: Res<LightCoverage>,
+    lamps: Res<Lamps>,
+    health_query: Query<&Health, With<Player>>,
+    hud_query: Query<(), With<Hud>>,
+) {
+    // coming back from another state finds the HUD still there
+    if !hud_query.is_empty() {
+        return;
+    }
+
     let font = asset_server.load("fonts/MatrixtypeDisplay-9MyE5.ttf");
     commands
-        .spawn(Node {
-            width: Val::Percent(95.0),
-            height: Val::Percent(20.0),
-            align_self: AlignSelf::FlexStart,
-            justify_self: JustifySelf::Center,
-            align_items: AlignItems::FlexStart,
-            justify_content: JustifyContent::SpaceBetween,
-            margin: UiRect {
-                top: Val::Vh(2.),
+        .spawn((
+            Node {
+                width: Val::Percent(95.0),
+                height: Val::Percent(20.0),
+                align_self: AlignSelf::FlexStart,
+                justify_self: JustifySelf::Center,
+                align_items: AlignItems::FlexStart,
+                align_content: AlignContent::FlexStart,
+                justify_content: JustifyContent::SpaceBetween,
+                // too many entries for a single row in the small window
+                flex_wrap: FlexWrap::Wrap,
+                row_gap: Val::Px(5.),
+                margin: UiRect {
+                    top: Val::Vh(2.),
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        })
+            Hud,
+        ))
         .with_children(|parent| {
             parent
                 .spawn((
@@ -63,14 +118,139 @@ fn setup_hud(mut commands: Commands, asset_server: Res<AssetServer>, mana_state:
                     ));
 
                     parent.spawn((
-                        Text::new("0"),
+                        Text::new(score.0.to_string()),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: 15.0,
+                            ..default()
+                        },
+                        TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                        ScoreValue,
+                    ));
+                });
+
+            parent
+                .spawn((
+                    Node {
+                        height: Val::Px(25.),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        padding: UiRect::horizontal(Val::Px(5.)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.3)),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new(seed_text(&seed)),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: 15.0,
+                            ..default()
+                        },
+                        TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                        SeedValue,
+                    ));
+                });
+
+            parent
+                .spawn((
+                    Node {
+                        height: Val::Px(25.),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        padding: UiRect::horizontal(Val::Px(5.)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.3)),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new(floor_text(&maze)),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: 15.0,
+                            ..default()
+                        },
+                        TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                        FloorValue,
+                    ));
+                });
+
+            parent
+                .spawn((
+                    Node {
+                        height: Val::Px(25.),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        padding: UiRect::horizontal(Val::Px(5.)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.3)),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new(coverage_text(&coverage)),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: 15.0,
+                            ..default()
+                        },
+                        TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                        CoverageValue,
+                    ));
+                });
+
+            parent
+                .spawn((
+                    Node {
+                        height: Val::Px(25.),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        padding: UiRect::horizontal(Val::Px(5.)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.3)),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new(lamp_text(&lamps)),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: 15.0,
+                            ..default()
+                        },
+                        TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                        LampValue,
+                    ));
+                });
+
+            parent
+                .spawn((
+                    Node {
+                        height: Val::Px(25.),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        padding: UiRect::horizontal(Val::Px(5.)),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.3)),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new(
+                            health_query
+                                .iter()
+                                .next()
+                                .map_or(String::new(), health_text),
+                        ),
                         TextFont {
                             font: font.clone(),
                             font_size: 15.0,
                             ..default()
                         },
                         TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
-                        ScoreValue(0.),
+                        HealthValue,
                     ));
                 });
 
@@ -101,25 +281,126 @@ fn setup_hud(mut commands: Commands, asset_server: Res<AssetServer>, mana_state:
         });
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn update_hud(
     time: Res<Time>,
     mut timer: ResMut<ScoreTimer>,
-    mut time_query: Query<(&mut Text, &mut ScoreValue)>,
+    mut score: ResMut<Score>,
+    mut time_query: Query<&mut Text, With<ScoreValue>>,
     mana_state: Res<ManaState>,
     mut mana_query: Query<&mut Node, With<ManaValue>>,
+    coverage: Res<LightCoverage>,
+    mut coverage_query: Query<&mut Text, (With<CoverageValue>, Without<ScoreValue>)>,
+    seed: Res<MazeSeed>,
+    mut seed_query: Query<
+        &mut Text,
+        (With<SeedValue>, Without<ScoreValue>, Without<CoverageValue>),
+    >,
+    lamps: Res<Lamps>,
+    mut lamp_query: Query<
+        &mut Text,
+        (
+            With<LampValue>,
+            Without<ScoreValue>,
+            Without<CoverageValue>,
+            Without<SeedValue>,
+        ),
+    >,
+    changed_health_query: Query<&Health, (With<Player>, Changed<Health>)>,
+    mut health_query: Query<
+        &mut Text,
+        (
+            With<HealthValue>,
+            Without<ScoreValue>,
+            Without<CoverageValue>,
+            Without<SeedValue>,
+            Without<LampValue>,
+        ),
+    >,
 ) {
     for mut mana_bar in &mut mana_query {
         mana_bar.width = Val::Percent(mana_state.percentage);
     }
 
+    if seed.is_changed() {
+        for mut text in &mut seed_query {
+            text.0 = seed_text(&seed);
+        }
+    }
+
+    if lamps.is_changed() {
+        for mut text in &mut lamp_query {
+            text.0 = lamp_text(&lamps);
+        }
+    }
+
+    for health in changed_health_query.iter() {
+        for mut text in &mut health_query {
+            text.0 = health_text(health);
+        }
+    }
+
+    if coverage.is_changed() {
+        for mut text in &mut coverage_query {
+            text.0 = coverage_text(&coverage);
+        }
+    }
+
     timer.0.tick(time.delta());
 
     if !timer.0.just_finished() {
         return;
     }
 
-    for (mut time_text, mut time_value) in &mut time_query {
-        time_value.0 += 1.;
-        time_text.0 = time_value.0.to_string();
+    score.0 += 1.;
+    for mut time_text in &mut time_query {
+        time_text.0 = score.0.to_string();
+    }
+}
+
+// Every maze is timed from zero
+fn reset_score(mut rebuilt: EventReader<MazeRebuilt>, mut score: ResMut<Score>) {
+    if rebuilt.is_empty() {
+        return;
+    }
+    rebuilt.clear();
+    score.0 = 0.;
+}
+
+// The maze shifts every tick, so the floor is only read when it can have changed
+fn update_floor_text(
+    mut rebuilt: EventReader<MazeRebuilt>,
+    mut floor_changed: EventReader<MazeFloorChanged>,
+    maze: Res<Maze>,
+    mut floor_query: Query<&mut Text, With<FloorValue>>,
+) {
+    if rebuilt.is_empty() && floor_changed.is_empty() {
+        return;
+    }
+    rebuilt.clear();
+    floor_changed.clear();
+
+    for mut text in &mut floor_query {
+        text.0 = floor_text(&maze);
     }
 }
+
+fn seed_text(seed: &MazeSeed) -> String {
+    format!("Seed: {}", seed.0)
+}
+
+fn floor_text(maze: &Maze) -> String {
+    format!("Floor: {}/{}", maze.current_floor + 1, maze.floors.len())
+}
+
+fn lamp_text(lamps: &Lamps) -> String {
+    format!("Lamps: {}", lamps.available)
+}
+
+fn health_text(health: &Health) -> String {
+    format!("Health: {}/{}", health.current, health.max)
+}
+
+fn coverage_text(coverage: &LightCoverage) -> String {
+    format!("Lit: {:.0}%", coverage.0)
+}
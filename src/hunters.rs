@@ -0,0 +1,184 @@
+use bevy::prelude::*;
+use rand::seq::IndexedRandom;
+
+use crate::{
+    gamestate::GameState,
+    maze::{Maze, MazeEdgeChanged, MazeFloorChanged, MazeRebuilt},
+    maze_graph::Direction,
+    npc::{closed_passages, facing, replan_route, reseed_npc_rng, walk_route, NpcRng},
+    player::{character_sprite, Player, PlayerAnimations, PlayerDamaged, PlayerState},
+};
+
+pub struct HunterPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for HunterPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            spawn_hunters::<S>
+                .after(reseed_npc_rng)
+                .run_if(in_state(self.state.clone())),
+        );
+        app.add_systems(
+            Update,
+            (
+                replan_hunters,
+                move_hunters,
+                update_hunter_animation,
+                hit_player,
+            )
+                .chain()
+                .after(spawn_hunters::<S>)
+                .run_if(in_state(self.state.clone())),
+        );
+        app.add_systems(OnEnter(GameState::GameOver), despawn_hunters);
+    }
+}
+
+const HUNTERS_PER_FLOOR: usize = 2;
+/// Fewest steps between the player and a freshly spawned hunter, ignoring walls
+const HUNTER_SPAWN_DISTANCE: usize = 10;
+
+/// Chases the player along the passages of the current floor
+#[derive(Component)]
+pub struct Hunter {
+    speed: f32,
+    /// Cells still to walk through, starting with the one the hunter last reached
+    route: Vec<usize>,
+    state: PlayerState,
+    direction: Direction,
+}
+
+// Every floor has its own hunters, which appear far from the player when they arrive
+#[allow(clippy::too_many_arguments)]
+fn spawn_hunters<S: States>(
+    mut commands: Commands,
+    state: Res<State<S>>,
+    mut rebuilt: EventReader<MazeRebuilt>,
+    mut floor_changed: EventReader<MazeFloorChanged>,
+    hunter_query: Query<Entity, With<Hunter>>,
+    player_query: Query<&Transform, With<Player>>,
+    maze: Res<Maze>,
+    mut rng: ResMut<NpcRng>,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+) {
+    // mazes built in the menus get their hunters once the game starts
+    let entered = state.is_changed() && hunter_query.is_empty();
+    if rebuilt.is_empty() && floor_changed.is_empty() && !entered {
+        return;
+    }
+    let rebuilding = !rebuilt.is_empty();
+    rebuilt.clear();
+    floor_changed.clear();
+
+    for entity in hunter_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    // a new maze moves the player back to the spawn later this frame
+    let player_cell = player_query
+        .iter()
+        .find_map(|transform| maze.cell_at(transform.translation.truncate()))
+        .filter(|_| !rebuilding)
+        .unwrap_or(maze.spawn);
+    let distances = maze.graph().distances(player_cell);
+    let cells: Vec<usize> = maze
+        .graph()
+        .cells()
+        .filter(|cell| distances[*cell].is_some_and(|d| d >= HUNTER_SPAWN_DISTANCE))
+        .collect();
+
+    for &cell in cells.choose_multiple(&mut rng.0, HUNTERS_PER_FLOOR) {
+        let (sprite, animations) = character_sprite(
+            "sprite/character/Prototype_Character_Red.png",
+            &asset_server,
+            &mut texture_atlases,
+        );
+        commands.spawn((
+            sprite,
+            animations,
+            Transform::from_translation(maze.cell_position(cell).extend(0.)),
+            Hunter {
+                speed: 120.0,
+                route: Vec::new(),
+                state: PlayerState::Idle,
+                direction: Direction::Down,
+            },
+        ));
+    }
+}
+
+// Routes follow the tree to the cell of the player, and are dropped once the
+// player moved on or the origin-shift closed a passage along them
+fn replan_hunters(
+    mut changes: EventReader<MazeEdgeChanged>,
+    mut hunter_query: Query<(&mut Hunter, &mut Transform)>,
+    player_query: Query<&Transform, (With<Player>, Without<Hunter>)>,
+    maze: Res<Maze>,
+) {
+    let closed = closed_passages(changes.read(), &maze);
+
+    let Some(target) = player_query
+        .iter()
+        .find_map(|transform| maze.cell_at(transform.translation.truncate()))
+    else {
+        return;
+    };
+
+    for (mut hunter, mut transform) in hunter_query.iter_mut() {
+        replan_route(&mut hunter.route, &mut transform, target, &closed, &maze);
+    }
+}
+
+fn move_hunters(
+    mut hunter_query: Query<(&mut Hunter, &mut Transform)>,
+    maze: Res<Maze>,
+    time: Res<Time>,
+) {
+    for (mut hunter, mut transform) in hunter_query.iter_mut() {
+        let distance = hunter.speed * time.delta_secs();
+        match walk_route(&mut hunter.route, &mut transform, distance, &maze) {
+            Some(heading) => {
+                hunter.direction = facing(heading);
+                hunter.state = PlayerState::Walking;
+            }
+            None => hunter.state = PlayerState::Idle,
+        }
+    }
+}
+
+fn update_hunter_animation(mut query: Query<(&Hunter, &mut PlayerAnimations, &mut Sprite)>) {
+    for (hunter, mut animations, mut sprite) in query.iter_mut() {
+        animations.play(hunter.state.clone(), hunter.direction, &mut sprite);
+    }
+}
+
+// Touching a hunter hurts the player
+fn hit_player(
+    hunter_query: Query<&Transform, With<Hunter>>,
+    player_query: Query<&Transform, With<Player>>,
+    maze: Res<Maze>,
+    mut damaged: EventWriter<PlayerDamaged>,
+) {
+    for player in player_query.iter() {
+        let hit = hunter_query.iter().any(|hunter| {
+            hunter
+                .translation
+                .truncate()
+                .distance(player.translation.truncate())
+                < maze.cell_size * 0.3
+        });
+        if hit {
+            damaged.send(PlayerDamaged { amount: 1 });
+        }
+    }
+}
+
+fn despawn_hunters(mut commands: Commands, hunter_query: Query<Entity, With<Hunter>>) {
+    for entity in hunter_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,28 +1,66 @@
+use abilities::AbilityPlugin;
 use bevy::prelude::*;
 use bevy_light_2d::plugin::Light2dPlugin;
 use bevy_prototype_lyon::prelude::*;
 use camera::CameraPlugin;
+use guards::GuardPlugin;
 use hud::HudPlugin;
+use hunters::HunterPlugin;
 use iyes_perf_ui::{entries::PerfUiFramerateEntries, prelude::*};
 
 use bevy_rapier2d::plugin::{NoUserData, RapierPhysicsPlugin};
 use gamestate::{GameState, GameStatePlugin};
 use maze::MazePlugin;
-use maze_specs::{MazeColor, MazeShape};
+use maze_generators::MazeAlgorithm;
+use maze_io::{MazeImport, MazeIoPlugin};
+use maze_mask::MazeMask;
+use maze_policies::MazeMutation;
+use maze_specs::{ExitDistance, MazeBraid, MazeColor, MazeFloors, MazeSeed, MazeShape, MazeWarmup};
+use maze_topology::Topology;
 use menu_screens::MenuPlugin;
+use npc::NpcPlugin;
+use objectives::ObjectivePlugin;
 use player::PlayerPlugin;
+use stealth::StealthPlugin;
+use teleports::TeleportPlugin;
 use walls::WallPlugin;
 
+mod abilities;
 mod camera;
 mod gamestate;
+mod guards;
 mod hud;
+mod hunters;
 mod maze;
+mod maze_generators;
+mod maze_graph;
+mod maze_io;
+mod maze_mask;
+mod maze_policies;
 mod maze_specs;
+mod maze_topology;
 mod menu_screens;
+mod npc;
+mod objectives;
 mod player;
+mod stealth;
+mod teleports;
 mod walls;
 
 fn main() {
+    // A maze file passed on the command line replaces the first generated maze
+    let import = std::env::args()
+        .nth(1)
+        .map_or_else(MazeImport::default, |path| MazeImport::load(&path));
+    let shape = import
+        .layout
+        .as_ref()
+        .map_or(Vec2::new(15., 15.), |layout| layout.size());
+    let topology = import
+        .layout
+        .as_ref()
+        .map_or(Topology::Square, |layout| layout.topology());
+
     App::new()
         .add_plugins((
             DefaultPlugins
@@ -50,8 +88,20 @@ fn main() {
             path_color: Color::srgb(0.2, 0.2, 0.2),
             wall_color: Color::srgb(0.8, 0.8, 0.8),
             player_color: Color::srgb(0.0, 0.0, 1.0),
+            exit_color: Color::srgb(0.2, 0.9, 0.3),
+            light_color: Color::srgb(1.0, 0.8, 0.4),
+            station_color: Color::srgb(0.4, 0.6, 1.0),
+            head_color: Color::srgb(0.9, 0.2, 0.2),
         })
-        .insert_resource(MazeShape(Vec2::new(15., 15.)))
+        .insert_resource(MazeShape(shape))
+        .insert_resource(MazeMask::Full)
+        .insert_resource(topology)
+        .insert_resource(import)
+        .insert_resource(MazeSeed(rand::random()))
+        .insert_resource(MazeWarmup(20_000))
+        .insert_resource(MazeFloors(3))
+        .insert_resource(MazeBraid(0.))
+        .insert_resource(ExitDistance(20))
         .insert_resource(MazeUpdateTimer(Timer::from_seconds(
             0.0125,
             TimerMode::Repeating,
@@ -60,6 +110,11 @@ fn main() {
         .add_plugins(CameraPlugin)
         .add_plugins(MazePlugin {
             state: GameState::InGame,
+            algorithm: MazeAlgorithm::Comb,
+            mutation: MazeMutation::LineOfSight,
+        })
+        .add_plugins(MazeIoPlugin {
+            state: GameState::InGame,
         })
         .add_plugins(WallPlugin {
             state: GameState::InGame,
@@ -70,14 +125,40 @@ fn main() {
         .add_plugins(HudPlugin {
             state: GameState::InGame,
         })
+        .add_plugins(ObjectivePlugin {
+            state: GameState::InGame,
+        })
+        .add_plugins(NpcPlugin)
+        .add_plugins(HunterPlugin {
+            state: GameState::InGame,
+        })
+        .add_plugins(GuardPlugin {
+            state: GameState::InGame,
+        })
+        .add_plugins(StealthPlugin {
+            state: GameState::InGame,
+        })
+        .add_plugins(AbilityPlugin)
+        .add_plugins(TeleportPlugin {
+            state: GameState::InGame,
+        })
         .add_plugins(MenuPlugin)
         .run();
 }
 
-fn setup(mut commands: Commands) {
+/// Marks the FPS counter, which is only spawned the first time the game starts
+#[derive(Component)]
+struct FpsCounter;
+
+fn setup(mut commands: Commands, counter_query: Query<(), With<FpsCounter>>) {
+    // coming back from the pause menu or a scan finds the counter still there
+    if !counter_query.is_empty() {
+        return;
+    }
     commands.spawn((
         // Contains everything related to FPS and frame time
         PerfUiFramerateEntries::default(),
+        FpsCounter,
     ));
 }
 
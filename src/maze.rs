@@ -1,17 +1,51 @@
-use std::cmp::min;
+use std::{
+    cmp::min,
+    collections::{HashSet, VecDeque},
+};
 
 use bevy::prelude::*;
-use rand::random_range;
+use rand::{
+    rngs::StdRng,
+    seq::{IndexedRandom, SliceRandom},
+    SeedableRng,
+};
+use serde::{Deserialize, Serialize};
 
-use crate::{maze_specs::MazeShape, player::Player, MazeUpdateTimer};
+use crate::{
+    maze_generators::{braid, MazeAlgorithm},
+    maze_graph::{Direction, Edge, EdgeChange, MazeGraph},
+    maze_io::MazeImport,
+    maze_mask::MazeMask,
+    maze_policies::{ActiveMutationPolicy, MazeMutation, MutationContext},
+    maze_specs::{ExitDistance, MazeBraid, MazeFloors, MazeSeed, MazeShape, MazeWarmup},
+    maze_topology::Topology,
+    player::Player,
+    stealth::PlayerLight,
+    MazeUpdateTimer,
+};
 
 pub struct MazePlugin<S: States> {
     pub state: S,
+    pub algorithm: MazeAlgorithm,
+    pub mutation: MazeMutation,
 }
 
 impl<S: States> Plugin for MazePlugin<S> {
     fn build(&self, app: &mut App) {
+        app.insert_resource(self.algorithm);
+        app.insert_resource(self.mutation);
+        app.insert_resource(ActiveMutationPolicy(self.mutation.policy()));
+        app.init_resource::<VisibleEdges>();
+        app.init_resource::<MazeHeadCall>();
+        app.add_event::<MazeEdgeChanged>();
+        app.add_event::<MazeRebuilt>();
+        app.add_event::<MazeFloorChanged>();
         app.add_systems(PreStartup, (setup_maze, build_maze).chain());
+        app.add_systems(Update, build_maze.run_if(maze_settings_changed));
+        app.add_systems(
+            Update,
+            apply_mutation_policy.run_if(resource_changed::<MazeMutation>),
+        );
         app.add_systems(Update, update_maze.run_if(in_state(self.state.clone())));
     }
 }
@@ -19,20 +53,236 @@ impl<S: States> Plugin for MazePlugin<S> {
 #[derive(Component)]
 pub struct MazeNode {
     pub position: Vec2,
-    pub index: Vec2,
-    pub parent: Option<Entity>,
+    pub index: usize,
+    /// Whether the player lit the cell, for every floor
+    pub lit: Vec<bool>,
 }
 
 #[derive(Resource, Debug)]
 pub struct Maze {
-    pub root: Entity,
-    pub grid: Vec<Vec<Entity>>,
+    /// Layers of the maze from the bottom up, all sharing the same grid
+    pub floors: Vec<MazeGraph>,
+    /// Floor the player is on, the only one with walls in the world
+    pub current_floor: usize,
+    pub stairs: Vec<Stairs>,
+    /// Cell the player starts in on the bottom floor
+    pub spawn: usize,
+    /// Cell on the top floor that ends the run
+    pub exit: usize,
     pub cell_size: f32,
     pub path_thickness: f32,
     pub view_distance: f32,
+    /// Offset that centres the maze on the world origin
+    pub origin: Vec2,
+}
+
+/// Staircase between a cell on `floor` and the same cell on the floor above it
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Stairs {
+    pub floor: usize,
+    pub cell: usize,
+}
+
+/// Sent for every passage the origin-shift opens or closes
+#[derive(Event, Debug, Clone, Copy)]
+pub struct MazeEdgeChanged {
+    pub floor: usize,
+    pub change: EdgeChange,
+}
+
+/// Edges of the current floor the player can see, kept up to date by the walls
+#[derive(Resource, Default)]
+pub struct VisibleEdges(pub HashSet<Edge>);
+
+/// Sent when the player moved to another floor
+#[derive(Event, Debug)]
+pub struct MazeFloorChanged;
+
+/// Sent whenever the whole maze got regenerated
+#[derive(Event, Debug)]
+pub struct MazeRebuilt;
+
+/// Pulls the root of a floor toward a cell, one step per update, until it
+/// arrives or runs out of steps
+#[derive(Resource, Default)]
+pub struct MazeHeadCall(pub Option<HeadCall>);
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeadCall {
+    pub floor: usize,
+    pub target: usize,
+    pub steps_left: usize,
+}
+
+/// Deterministic random source for everything that shapes the maze, seeded from [`MazeSeed`]
+#[derive(Resource)]
+pub struct MazeRng(pub StdRng);
+
+const STAIRS_PER_FLOOR: usize = 2;
+/// Shortest walk from the spawn to the exit, through the floors in between
+const MIN_SPAWN_EXIT_DISTANCE: usize = 15;
+/// Shortest walk from the spawn to the head of the bottom floor
+const MIN_SPAWN_ROOT_DISTANCE: usize = 5;
+
+impl Maze {
+    /// Graph of the floor the player is on
+    pub fn graph(&self) -> &MazeGraph {
+        &self.floors[self.current_floor]
+    }
+
+    /// Floor reached by taking the stairs in `cell` from the current floor
+    pub fn stairs_destination(&self, cell: usize) -> Option<usize> {
+        self.stairs.iter().find_map(|stairs| {
+            if stairs.cell != cell {
+                None
+            } else if stairs.floor == self.current_floor {
+                Some(stairs.floor + 1)
+            } else if stairs.floor + 1 == self.current_floor {
+                Some(stairs.floor)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Steps along open passages and stairs from a cell on the current floor to
+    /// every cell of every floor, `None` for cells the walls cut off
+    pub fn walking_distances(&self, start: usize) -> Vec<Vec<Option<usize>>> {
+        let mut distances = vec![vec![None; self.graph().cell_count()]; self.floors.len()];
+        distances[self.current_floor][start] = Some(0);
+
+        let mut queue = VecDeque::from([(self.current_floor, start)]);
+        while let Some((floor, cell)) = queue.pop_front() {
+            let distance = distances[floor][cell].map(|distance| distance + 1);
+            let passages = self.floors[floor]
+                .open_neighbours(cell)
+                .map(|next| (floor, next));
+            let stairs = self.stairs.iter().filter_map(|stairs| {
+                if stairs.cell != cell {
+                    None
+                } else if stairs.floor == floor {
+                    Some((floor + 1, cell))
+                } else if stairs.floor + 1 == floor {
+                    Some((stairs.floor, cell))
+                } else {
+                    None
+                }
+            });
+
+            let next: Vec<(usize, usize)> = passages.chain(stairs).collect();
+            for (floor, cell) in next {
+                if distances[floor][cell].is_none() {
+                    distances[floor][cell] = distance;
+                    queue.push_back((floor, cell));
+                }
+            }
+        }
+
+        distances
+    }
+
+    /// Cell containing a world position, if it lies inside the grid
+    pub fn cell_at(&self, position: Vec2) -> Option<usize> {
+        let graph = self.graph();
+        let coords = graph
+            .topology()
+            .closest(position + self.origin, self.cell_size);
+        (coords.x >= 0
+            && coords.y >= 0
+            && coords.x < graph.width() as i32
+            && coords.y < graph.height() as i32)
+            .then(|| graph.index(coords.x as usize, coords.y as usize))
+    }
+
+    /// Enabled cell of the current floor whose centre is closest to a world position
+    pub fn nearest_cell(&self, position: Vec2) -> Option<usize> {
+        self.graph().cells().min_by(|a, b| {
+            let (a, b) = (self.cell_position(*a), self.cell_position(*b));
+            a.distance_squared(position)
+                .total_cmp(&b.distance_squared(position))
+        })
+    }
+
+    /// World position of the centre of a cell
+    pub fn cell_position(&self, cell: usize) -> Vec2 {
+        self.graph()
+            .topology()
+            .centre(self.coords(cell), self.cell_size)
+            - self.origin
+    }
+
+    /// World position of the middle of the side an edge lies on, and the direction along that side
+    pub fn edge_segment(&self, edge: Edge) -> (Vec2, Vec2) {
+        let topology = self.graph().topology();
+        let coords = self.coords(edge.cell);
+        // the cell on the other side may be outside the grid, its centre still exists
+        let other = topology
+            .neighbour(coords, edge.direction)
+            .expect("edge in a direction the cell has no side in");
+
+        let from = topology.centre(coords, self.cell_size);
+        let to = topology.centre(other, self.cell_size);
+        (
+            (from + to) * 0.5 - self.origin,
+            (to - from).normalize().perp(),
+        )
+    }
+
+    /// Offset from the centre of the cell around `position` to the centre of the
+    /// cell on its side facing `towards` the most
+    pub fn neighbour_offset(&self, position: Vec2, towards: Vec2) -> Vec2 {
+        let topology = self.graph().topology();
+        let coords = topology.closest(position + self.origin, self.cell_size);
+        let centre = topology.centre(coords, self.cell_size);
+
+        topology
+            .directions(coords)
+            .iter()
+            .filter_map(|direction| topology.neighbour(coords, *direction))
+            .map(|other| topology.centre(other, self.cell_size) - centre)
+            .max_by(|a, b| {
+                a.normalize()
+                    .dot(towards)
+                    .total_cmp(&b.normalize().dot(towards))
+            })
+            .unwrap_or_default()
+    }
+
+    /// Area covered by the cells of the maze, including disabled ones
+    pub fn bounds(&self) -> Rect {
+        let topology = self.graph().topology();
+        (0..self.graph().cell_count())
+            .flat_map(|cell| {
+                let centre = self.cell_position(cell);
+                topology
+                    .corners(self.coords(cell), self.cell_size)
+                    .into_iter()
+                    .map(move |corner| centre + corner)
+            })
+            .fold(Rect::EMPTY, |bounds, point| bounds.union_point(point))
+    }
+
+    fn coords(&self, cell: usize) -> IVec2 {
+        let (x, y) = self.graph().coords(cell);
+        IVec2::new(x as i32, y as i32)
+    }
+
+    /// Centres the cells of the current topology on the world origin
+    fn update_origin(&mut self) {
+        let topology = self.graph().topology();
+        let centres = (0..self.graph().cell_count())
+            .map(|cell| topology.centre(self.coords(cell), self.cell_size))
+            .fold(Rect::EMPTY, |bounds, centre| bounds.union_point(centre));
+        self.origin = centres.center();
+    }
 }
 
-fn setup_maze(mut commands: Commands, shape: Res<MazeShape>, window: Query<&Window>) {
+fn setup_maze(
+    mut commands: Commands,
+    shape: Res<MazeShape>,
+    topology: Res<Topology>,
+    window: Query<&Window>,
+) {
     let window = window.single();
 
     let cell_size = min(
@@ -40,161 +290,366 @@ fn setup_maze(mut commands: Commands, shape: Res<MazeShape>, window: Query<&Wind
         (window.height() / shape.0.y) as i32,
     ) as f32;
 
+    let (width, height) = (shape.0.x as usize, shape.0.y as usize);
     let mut maze = Maze {
-        root: Entity::PLACEHOLDER,
-        grid: vec![vec![Entity::from_raw(0); shape.0.x as usize]; shape.0.y as usize],
+        floors: vec![MazeGraph::new(
+            width,
+            height,
+            *topology,
+            vec![true; width * height],
+        )],
+        current_floor: 0,
+        stairs: Vec::new(),
+        spawn: 0,
+        exit: 0,
         cell_size,
         path_thickness: cell_size * 0.8,
         view_distance: cell_size * 3.0,
+        origin: Vec2::ZERO,
     };
+    maze.update_origin();
 
-    for y in 0..maze.grid.len() {
-        for x in 0..maze.grid[0].len() {
-            let entity = commands
-                .spawn((
-                    MazeNode {
-                        position: Vec2::new(
-                            x as f32 * maze.cell_size
-                                - maze.grid[0].len() as f32 * 0.5 * maze.cell_size
-                                + (maze.cell_size * 0.5),
-                            y as f32 * maze.cell_size
-                                - maze.grid.len() as f32 * 0.5 * maze.cell_size
-                                + (maze.cell_size * 0.5),
-                        ),
-                        index: Vec2::new(x as f32, y as f32),
-                        parent: None,
-                    },
-                    Transform::from_translation(Vec3::new(
-                        x as f32 * maze.cell_size
-                            - maze.grid[0].len() as f32 * 0.5 * maze.cell_size
-                            + (maze.cell_size * 0.5),
-                        y as f32 * maze.cell_size - maze.grid.len() as f32 * 0.5 * maze.cell_size
-                            + (maze.cell_size * 0.5),
-                        0.0,
-                    )),
-                ))
-                .id();
-            maze.grid[y][x] = entity;
-        }
+    for index in 0..maze.graph().cell_count() {
+        let position = maze.cell_position(index);
+        commands.spawn((
+            MazeNode {
+                position,
+                index,
+                lit: Vec::new(),
+            },
+            Transform::from_translation(position.extend(0.0)),
+            Visibility::default(),
+        ));
     }
 
     commands.insert_resource(maze);
 }
 
-fn build_maze(mut maze: ResMut<Maze>, mut query: Query<&mut MazeNode>) {
-    for y in 0..maze.grid.len() {
-        for x in 0..maze.grid[0].len() {
-            // check if there is a next node in x direction
-            if x + 1 < maze.grid[0].len() {
-                let next_node = maze.grid[y][x + 1];
-                if let Ok(mut current_node) = query.get_mut(maze.grid[y][x]) {
-                    current_node.parent = Some(next_node)
+#[allow(clippy::too_many_arguments)]
+fn build_maze(
+    mut commands: Commands,
+    mut maze: ResMut<Maze>,
+    seed: Res<MazeSeed>,
+    algorithm: Res<MazeAlgorithm>,
+    mask: Res<MazeMask>,
+    topology: Res<Topology>,
+    floors: Res<MazeFloors>,
+    braid_ratio: Res<MazeBraid>,
+    exit_distance: Res<ExitDistance>,
+    warmup: Res<MazeWarmup>,
+    import: Res<MazeImport>,
+    mut head_call: ResMut<MazeHeadCall>,
+    mut node_query: Query<(&mut MazeNode, &mut Transform)>,
+    mut rebuilt: EventWriter<MazeRebuilt>,
+) {
+    let mut rng = StdRng::seed_from_u64(seed.0);
+
+    let (width, height) = (maze.graph().width(), maze.graph().height());
+    let enabled = mask.cells(width, height);
+
+    maze.current_floor = 0;
+    head_call.0 = None;
+    if let Some(layout) = import.layout.clone() {
+        // a loaded layout replaces every generated maze, a new seed only moves the spawn and exit
+        maze.floors = layout.floors;
+        maze.stairs = layout.stairs;
+    } else {
+        maze.floors = (0..floors.0.max(1))
+            .map(|_| {
+                let mut graph = MazeGraph::new(width, height, *topology, enabled.clone());
+                let edges = algorithm.generator().generate(&graph, &mut rng);
+                graph.set_tree(&edges, graph.root());
+
+                // Let the origin-shift run unconstrained so the player starts in an already shuffled maze
+                for _ in 0..warmup.0 {
+                    let neighbours: Vec<Direction> = graph
+                        .neighbours(graph.root())
+                        .map(|(direction, _)| direction)
+                        .collect();
+                    let Some(&direction) = neighbours.choose(&mut rng) else {
+                        break;
+                    };
+                    graph.shift_root(direction);
                 }
-            }
+
+                // loops go on top of the tree, which keeps shifting underneath them
+                braid(&mut graph, braid_ratio.0, &mut rng);
+
+                graph
+            })
+            .collect();
+
+        // every floor is entered and left through the same cells on both sides of the stairs,
+        // so the stairs up can not share a cell with the stairs coming up from below
+        let cells: Vec<usize> = maze.graph().cells().collect();
+        let mut stairs: Vec<Stairs> = Vec::new();
+        for floor in 0..maze.floors.len() - 1 {
+            let free: Vec<usize> = cells
+                .iter()
+                .copied()
+                .filter(|cell| {
+                    !stairs
+                        .iter()
+                        .any(|below| below.floor + 1 == floor && below.cell == *cell)
+                })
+                .collect();
+            stairs.extend(
+                free.choose_multiple(&mut rng, STAIRS_PER_FLOOR)
+                    .map(|&cell| Stairs { floor, cell }),
+            );
         }
-        // check if there is a next node in -y direction
-        if y + 1 < maze.grid.len() {
-            let next_node = maze.grid[y + 1][maze.grid[0].len() - 1];
-            if let Ok(mut current_node) = query.get_mut(maze.grid[y][maze.grid[0].len() - 1]) {
-                current_node.parent = Some(next_node)
-            }
+        maze.stairs = stairs;
+    }
+
+    // a new topology moves every cell
+    maze.update_origin();
+    for (mut node, mut transform) in node_query.iter_mut() {
+        node.position = maze.cell_position(node.index);
+        node.lit = vec![false; maze.floors.len()];
+        transform.translation = node.position.extend(0.0);
+    }
+
+    let (spawn, exit) = place_spawn_and_exit(&maze, exit_distance.0, &mut rng);
+    maze.spawn = spawn;
+    maze.exit = exit;
+
+    commands.insert_resource(MazeRng(rng));
+    rebuilt.send(MazeRebuilt);
+}
+
+/// Picks a random spawn on the bottom floor away from dead ends and the head,
+/// with an exit on the top floor that is a long enough walk away.
+///
+/// Falls back on the spawn with the longest walk to its exit when no spawn meets every
+/// constraint, and on the cell closest to the centre when there is no candidate at all.
+fn place_spawn_and_exit(maze: &Maze, exit_distance: usize, rng: &mut StdRng) -> (usize, usize) {
+    let graph = maze.graph();
+    let top = maze.floors.last().expect("maze without floors");
+    let root_distances = maze.walking_distances(graph.root());
+
+    let mut candidates: Vec<usize> = graph
+        .cells()
+        .filter(|cell| graph.open_neighbours(*cell).count() > 1)
+        .filter(|cell| {
+            root_distances[maze.current_floor][*cell]
+                .is_some_and(|distance| distance >= MIN_SPAWN_ROOT_DISTANCE)
+        })
+        .collect();
+    candidates.shuffle(rng);
+
+    // the exit goes on the top floor, as close to the wanted distance as the region of the spawn allows
+    let exit_for = |spawn: usize| {
+        top.distances(spawn)
+            .iter()
+            .enumerate()
+            .filter(|(cell, _)| top.is_enabled(*cell))
+            .filter_map(|(cell, distance)| Some((cell, (*distance)?)))
+            .min_by_key(|(_, distance)| distance.abs_diff(exit_distance))
+            .map_or(spawn, |(cell, _)| cell)
+    };
+
+    let mut best: Option<(usize, usize, usize)> = None;
+    for spawn in candidates {
+        let exit = exit_for(spawn);
+        let walk = maze.walking_distances(spawn)[maze.floors.len() - 1][exit].unwrap_or(0);
+        if walk >= MIN_SPAWN_EXIT_DISTANCE {
+            return (spawn, exit);
+        }
+        if best.is_none_or(|(_, _, longest)| walk > longest) {
+            best = Some((spawn, exit, walk));
         }
     }
 
-    // set root
-    maze.root = maze.grid[maze.grid.len() - 1][maze.grid[0].len() - 1];
+    best.map(|(spawn, exit, _)| (spawn, exit))
+        .unwrap_or_else(|| {
+            let spawn = maze.nearest_cell(Vec2::ZERO).expect("maze without cells");
+            (spawn, exit_for(spawn))
+        })
 }
 
+// Rebuild the maze when its seed, algorithm, mask, topology or braiding got changed from the menu.
+// A loaded maze is not generated, so only a new seed rebuilds it.
+fn maze_settings_changed(
+    seed: Res<MazeSeed>,
+    algorithm: Res<MazeAlgorithm>,
+    mask: Res<MazeMask>,
+    topology: Res<Topology>,
+    braid_ratio: Res<MazeBraid>,
+    import: Res<MazeImport>,
+) -> bool {
+    let generator_changed = (algorithm.is_changed() && !algorithm.is_added())
+        || (mask.is_changed() && !mask.is_added())
+        || (topology.is_changed() && !topology.is_added())
+        || (braid_ratio.is_changed() && !braid_ratio.is_added());
+
+    (seed.is_changed() && !seed.is_added()) || (generator_changed && import.layout.is_none())
+}
+
+fn apply_mutation_policy(mutation: Res<MazeMutation>, mut policy: ResMut<ActiveMutationPolicy>) {
+    policy.0 = mutation.policy();
+}
+
+#[allow(clippy::too_many_arguments)]
 fn update_maze(
     mut maze: ResMut<Maze>,
-    mut query: Query<&mut MazeNode>,
+    mut rng: ResMut<MazeRng>,
+    mut policy: ResMut<ActiveMutationPolicy>,
+    visible_edges: Res<VisibleEdges>,
+    mut head_call: ResMut<MazeHeadCall>,
     time: Res<Time>,
     mut timer: ResMut<MazeUpdateTimer>,
     player_query: Query<&Transform, With<Player>>,
+    player_light: Res<PlayerLight>,
+    mut edge_changed: EventWriter<MazeEdgeChanged>,
 ) {
     if !timer.0.tick(time.delta()).just_finished() {
         return;
     }
-    if let Ok(mut root_node) = query.get_mut(maze.root) {
-        let player_pos = player_query.single().translation.truncate();
-        let available_dirs = get_available_dir(
-            root_node.index,
-            root_node.position,
-            (maze.grid[0].len(), maze.grid.len()),
-            &maze,
-            player_pos,
-        );
 
-        if !available_dirs.is_empty() {
-            let random_index = random_range(0..available_dirs.len());
+    let player_pos = player_query.single().translation.truncate();
 
-            let new_root = match available_dirs[random_index] {
-                Direction::Up => {
-                    maze.grid[root_node.index.y as usize - 1][root_node.index.x as usize]
-                }
-                Direction::Down => {
-                    maze.grid[root_node.index.y as usize + 1][root_node.index.x as usize]
-                }
-                Direction::Left => {
-                    maze.grid[root_node.index.y as usize][root_node.index.x as usize - 1]
-                }
-                Direction::Right => {
-                    maze.grid[root_node.index.y as usize][root_node.index.x as usize + 1]
-                }
+    // every floor has its own head, only the one on the player's floor follows the policy
+    for floor in 0..maze.floors.len() {
+        let graph = &maze.floors[floor];
+        let candidates: Vec<(Direction, usize)> = graph.neighbours(graph.root()).collect();
+
+        let call = head_call.0.filter(|call| call.floor == floor);
+        let step = if let Some(call) = call {
+            // a called head ignores the policy and takes the shortest way to its target
+            let distances = graph.distances(call.target);
+            candidates
+                .iter()
+                .min_by_key(|(_, cell)| distances[*cell].unwrap_or(usize::MAX))
+        } else if floor == maze.current_floor {
+            let context = MutationContext {
+                maze: &maze,
+                player_position: player_pos,
+                view_distance: player_light.view_distance(&maze),
+                visible_edges: &visible_edges.0,
+                elapsed: time.elapsed_secs(),
             };
-            root_node.parent = Some(new_root);
+            if policy.0.should_shift(&context) {
+                candidates
+                    .choose_weighted(&mut rng.0, |(_, cell)| policy.0.weight(&context, *cell))
+                    .ok()
+            } else {
+                None
+            }
+        } else {
+            candidates.choose(&mut rng.0)
+        };
 
-            {
-                if let Ok(mut new_root_node) = query.get_mut(new_root) {
-                    new_root_node.parent = None;
-                    maze.root = new_root;
-                }
+        if let Some(&(direction, _)) = step {
+            for change in maze.floors[floor].shift_root(direction) {
+                edge_changed.send(MazeEdgeChanged { floor, change });
             }
         }
+
+        if let Some(call) = call {
+            let arrived = maze.floors[floor].root() == call.target;
+            head_call.0 = (!arrived && call.steps_left > 1).then_some(HeadCall {
+                steps_left: call.steps_left - 1,
+                ..call
+            });
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
-pub enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corridor(edges: &[(usize, usize)]) -> MazeGraph {
+        let mut graph = MazeGraph::new(3, 1, Topology::Square, vec![true; 3]);
+        graph.set_tree(edges, 0);
+        graph
+    }
+
+    fn maze(floors: Vec<MazeGraph>, stairs: Vec<Stairs>) -> Maze {
+        Maze {
+            floors,
+            current_floor: 0,
+            stairs,
+            spawn: 0,
+            exit: 0,
+            cell_size: 10.,
+            path_thickness: 8.,
+            view_distance: 30.,
+            origin: Vec2::ZERO,
+        }
+    }
 
-fn get_available_dir(
-    root_index: Vec2,
-    root_position: Vec2,
-    maze_shape: (usize, usize),
-    maze: &Maze,
-    player_position: Vec2,
-) -> Vec<Direction> {
-    let mut available_dirs = Vec::new();
-
-    if root_index.y > 0.0
-        && player_position.distance(root_position + Vec2::new(0., maze.cell_size))
-            > maze.view_distance
-    {
-        available_dirs.push(Direction::Up);
-    }
-    if root_index.y < maze_shape.1 as f32 - 1.0
-        && player_position.distance(root_position + Vec2::new(0., -maze.cell_size))
-            > maze.view_distance
-    {
-        available_dirs.push(Direction::Down);
-    }
-    if root_index.x > 0.0
-        && player_position.distance(root_position + Vec2::new(-maze.cell_size, 0.))
-            > maze.view_distance
-    {
-        available_dirs.push(Direction::Left);
-    }
-    if root_index.x < maze_shape.0 as f32 - 1.0
-        && player_position.distance(root_position + Vec2::new(maze.cell_size, 0.))
-            > maze.view_distance
-    {
-        available_dirs.push(Direction::Right);
-    }
-
-    available_dirs
+    #[test]
+    fn walking_distances_take_the_stairs() {
+        let maze = maze(
+            vec![corridor(&[(0, 1), (1, 2)]), corridor(&[(0, 1), (1, 2)])],
+            vec![Stairs { floor: 0, cell: 2 }],
+        );
+        let distances = maze.walking_distances(0);
+
+        assert_eq!(distances[0], vec![Some(0), Some(1), Some(2)]);
+        assert_eq!(distances[1], vec![Some(5), Some(4), Some(3)]);
+    }
+
+    #[test]
+    fn walking_distances_stop_at_walls() {
+        let maze = maze(
+            vec![corridor(&[(0, 1), (1, 2)]), corridor(&[(0, 1)])],
+            vec![Stairs { floor: 0, cell: 2 }],
+        );
+        let distances = maze.walking_distances(0);
+
+        assert_eq!(distances[1], vec![None, None, Some(3)]);
+    }
+
+    #[test]
+    fn walking_distances_from_an_upper_floor_go_down_the_stairs() {
+        let mut maze = maze(
+            vec![corridor(&[(0, 1), (1, 2)]), corridor(&[(0, 1), (1, 2)])],
+            vec![Stairs { floor: 0, cell: 0 }],
+        );
+        maze.current_floor = 1;
+        let distances = maze.walking_distances(2);
+
+        assert_eq!(distances[1], vec![Some(2), Some(1), Some(0)]);
+        assert_eq!(distances[0], vec![Some(3), Some(4), Some(5)]);
+    }
+
+    fn generated_maze(seed: u64) -> Maze {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let floors = (0..3)
+            .map(|_| {
+                let mut graph = MazeGraph::new(10, 10, Topology::Square, vec![true; 100]);
+                let edges = MazeAlgorithm::Prim.generator().generate(&graph, &mut rng);
+                graph.set_tree(&edges, graph.root());
+                graph
+            })
+            .collect();
+        maze(
+            floors,
+            vec![Stairs { floor: 0, cell: 45 }, Stairs { floor: 1, cell: 54 }],
+        )
+    }
+
+    #[test]
+    fn spawn_and_exit_are_reproducible_from_the_seed() {
+        let maze = generated_maze(5);
+
+        let first = place_spawn_and_exit(&maze, 20, &mut StdRng::seed_from_u64(11));
+        let second = place_spawn_and_exit(&maze, 20, &mut StdRng::seed_from_u64(11));
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn spawn_keeps_away_from_dead_ends_the_head_and_the_exit() {
+        let maze = generated_maze(5);
+        let (spawn, exit) = place_spawn_and_exit(&maze, 20, &mut StdRng::seed_from_u64(11));
+
+        assert!(maze.graph().open_neighbours(spawn).count() > 1);
+        assert!(maze.walking_distances(maze.graph().root())[0][spawn]
+            .is_some_and(|distance| distance >= MIN_SPAWN_ROOT_DISTANCE));
+        assert!(maze.walking_distances(spawn)[2][exit]
+            .is_some_and(|distance| distance >= MIN_SPAWN_EXIT_DISTANCE));
+    }
 }
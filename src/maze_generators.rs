@@ -0,0 +1,499 @@
+use std::{collections::BTreeMap, iter::once};
+
+use bevy::prelude::*;
+use rand::{
+    rngs::StdRng,
+    seq::{IndexedRandom, SliceRandom},
+    Rng,
+};
+
+use crate::maze_graph::{Direction, MazeGraph};
+
+/// Produces the initial spanning tree of a maze.
+///
+/// Generators only return the undirected edges of the tree; the graph orients
+/// them towards its root so every algorithm ends up with the same parent
+/// pointer layout the origin-shift works on. Disabled cells are skipped, a
+/// mask splitting the grid results in one tree per region.
+pub trait MazeGenerator {
+    fn generate(&self, graph: &MazeGraph, rng: &mut StdRng) -> Vec<(usize, usize)>;
+}
+
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MazeAlgorithm {
+    #[default]
+    Comb,
+    RecursiveBacktracker,
+    Prim,
+    Kruskal,
+    Wilson,
+    Eller,
+}
+
+impl MazeAlgorithm {
+    pub const ALL: [MazeAlgorithm; 6] = [
+        MazeAlgorithm::Comb,
+        MazeAlgorithm::RecursiveBacktracker,
+        MazeAlgorithm::Prim,
+        MazeAlgorithm::Kruskal,
+        MazeAlgorithm::Wilson,
+        MazeAlgorithm::Eller,
+    ];
+
+    pub fn generator(self) -> Box<dyn MazeGenerator> {
+        match self {
+            MazeAlgorithm::Comb => Box::new(Comb),
+            MazeAlgorithm::RecursiveBacktracker => Box::new(RecursiveBacktracker),
+            MazeAlgorithm::Prim => Box::new(Prim),
+            MazeAlgorithm::Kruskal => Box::new(Kruskal),
+            MazeAlgorithm::Wilson => Box::new(Wilson),
+            MazeAlgorithm::Eller => Box::new(Eller),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MazeAlgorithm::Comb => "Comb",
+            MazeAlgorithm::RecursiveBacktracker => "Backtracker",
+            MazeAlgorithm::Prim => "Prim",
+            MazeAlgorithm::Kruskal => "Kruskal",
+            MazeAlgorithm::Wilson => "Wilson",
+            MazeAlgorithm::Eller => "Eller",
+        }
+    }
+
+    pub fn next(self) -> MazeAlgorithm {
+        let index = Self::ALL.iter().position(|a| *a == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// Serpentine rows all flowing to the right and up at the end of the row
+pub struct Comb;
+
+impl MazeGenerator for Comb {
+    fn generate(&self, graph: &MazeGraph, rng: &mut StdRng) -> Vec<(usize, usize)> {
+        let (width, height) = (graph.width(), graph.height());
+        let mut edges = Vec::new();
+
+        for y in 0..height {
+            for x in 0..width {
+                let cell = graph.index(x, y);
+                if let Some(next) = graph.neighbour(cell, Direction::Right) {
+                    edges.push((cell, next));
+                }
+            }
+            // not every cell has a side facing up on triangular grids
+            let last = (0..width)
+                .rev()
+                .find_map(|x| Some((graph.index(x, y), above(graph, graph.index(x, y))?)));
+            if let Some(link) = last {
+                edges.push(link);
+            }
+        }
+
+        // masked rows are not joined through the last column
+        complete_forest(graph, edges, rng)
+    }
+}
+
+/// Randomized depth-first search, giving long winding corridors
+pub struct RecursiveBacktracker;
+
+impl MazeGenerator for RecursiveBacktracker {
+    fn generate(&self, graph: &MazeGraph, rng: &mut StdRng) -> Vec<(usize, usize)> {
+        let mut edges = Vec::new();
+        let mut visited = vec![false; graph.cell_count()];
+
+        for start in random_starts(graph, rng) {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            let mut stack = vec![start];
+
+            while let Some(&cell) = stack.last() {
+                let unvisited: Vec<usize> = graph
+                    .neighbours(cell)
+                    .map(|(_, neighbour)| neighbour)
+                    .filter(|neighbour| !visited[*neighbour])
+                    .collect();
+
+                let Some(&next) = unvisited.choose(rng) else {
+                    stack.pop();
+                    continue;
+                };
+
+                visited[next] = true;
+                edges.push((cell, next));
+                stack.push(next);
+            }
+        }
+
+        edges
+    }
+}
+
+/// Randomized Prim, growing the tree from a random frontier edge
+pub struct Prim;
+
+impl MazeGenerator for Prim {
+    fn generate(&self, graph: &MazeGraph, rng: &mut StdRng) -> Vec<(usize, usize)> {
+        let mut edges = Vec::new();
+        let mut visited = vec![false; graph.cell_count()];
+
+        for start in random_starts(graph, rng) {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            let mut frontier: Vec<(usize, usize)> = graph
+                .neighbours(start)
+                .map(|(_, neighbour)| (start, neighbour))
+                .collect();
+
+            while !frontier.is_empty() {
+                let (from, to) = frontier.swap_remove(rng.random_range(0..frontier.len()));
+                if visited[to] {
+                    continue;
+                }
+
+                visited[to] = true;
+                edges.push((from, to));
+                frontier.extend(
+                    graph
+                        .neighbours(to)
+                        .filter(|(_, neighbour)| !visited[*neighbour])
+                        .map(|(_, neighbour)| (to, neighbour)),
+                );
+            }
+        }
+
+        edges
+    }
+}
+
+/// Randomized Kruskal, joining cells over shuffled edges
+pub struct Kruskal;
+
+impl MazeGenerator for Kruskal {
+    fn generate(&self, graph: &MazeGraph, rng: &mut StdRng) -> Vec<(usize, usize)> {
+        complete_forest(graph, Vec::new(), rng)
+    }
+}
+
+/// Wilson's loop-erased random walks, giving a uniformly random tree
+pub struct Wilson;
+
+impl MazeGenerator for Wilson {
+    fn generate(&self, graph: &MazeGraph, rng: &mut StdRng) -> Vec<(usize, usize)> {
+        let mut edges = Vec::new();
+        let mut in_tree = vec![false; graph.cell_count()];
+        let mut walk = vec![0; graph.cell_count()];
+
+        // every region needs a cell to grow its tree from
+        for component in graph.components() {
+            if let Some(&cell) = component.choose(rng) {
+                in_tree[cell] = true;
+            }
+        }
+
+        let cells: Vec<usize> = graph.cells().collect();
+        for start in cells {
+            // random walk until the tree is hit, only remembering the last exit
+            // of every cell erases the loops
+            let mut cell = start;
+            while !in_tree[cell] {
+                let neighbours: Vec<usize> = graph
+                    .neighbours(cell)
+                    .map(|(_, neighbour)| neighbour)
+                    .collect();
+                // a cell outside the tree always has a neighbour in its region
+                walk[cell] = *neighbours.choose(rng).unwrap();
+                cell = walk[cell];
+            }
+
+            let mut cell = start;
+            while !in_tree[cell] {
+                in_tree[cell] = true;
+                edges.push((cell, walk[cell]));
+                cell = walk[cell];
+            }
+        }
+
+        edges
+    }
+}
+
+/// Eller's row-by-row algorithm
+pub struct Eller;
+
+impl MazeGenerator for Eller {
+    fn generate(&self, graph: &MazeGraph, rng: &mut StdRng) -> Vec<(usize, usize)> {
+        let (width, height) = (graph.width(), graph.height());
+        let mut edges = Vec::new();
+        let mut sets = DisjointSets::new(graph.cell_count());
+
+        for y in 0..height {
+            let last_row = y + 1 == height;
+
+            // randomly join neighbouring cells of different sets, the last row joins all of them
+            for x in 0..width {
+                let a = graph.index(x, y);
+                let Some(b) = graph.neighbour(a, Direction::Right) else {
+                    continue;
+                };
+                if (last_row || rng.random_bool(0.5)) && sets.union(a, b) {
+                    edges.push((a, b));
+                }
+            }
+
+            if last_row {
+                break;
+            }
+
+            // every set carries on into the next row at least once
+            let mut row_sets: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+            for x in 0..width {
+                let cell = graph.index(x, y);
+                if above(graph, cell).is_some() {
+                    row_sets.entry(sets.find(cell)).or_default().push(cell);
+                }
+            }
+
+            for cells in row_sets.values_mut() {
+                cells.shuffle(rng);
+                let count = rng.random_range(1..=cells.len());
+                for &cell in cells.iter().take(count) {
+                    // hexagons of one set can share the cell above them
+                    if let Some(upper) = above(graph, cell).filter(|upper| sets.union(cell, *upper))
+                    {
+                        edges.push((cell, upper));
+                    }
+                }
+            }
+        }
+
+        // sets that could not carry on because of the mask still need joining
+        complete_forest(graph, edges, rng)
+    }
+}
+
+/// Removes about `ratio` of the dead ends by opening an extra passage out of them,
+/// preferring passages into other dead ends so one loop gets rid of both
+pub fn braid(graph: &mut MazeGraph, ratio: f32, rng: &mut StdRng) {
+    let mut cells: Vec<usize> = graph.cells().collect();
+    cells.shuffle(rng);
+
+    for cell in cells {
+        if !is_dead_end(graph, cell) || !rng.random_bool(ratio.clamp(0., 1.) as f64) {
+            continue;
+        }
+
+        let closed: Vec<usize> = graph
+            .neighbours(cell)
+            .map(|(_, neighbour)| neighbour)
+            .filter(|neighbour| !graph.is_open(graph.edge(cell, *neighbour)))
+            .collect();
+        let dead_ends: Vec<usize> = closed
+            .iter()
+            .copied()
+            .filter(|neighbour| is_dead_end(graph, *neighbour))
+            .collect();
+
+        let target = dead_ends.choose(rng).or_else(|| closed.choose(rng));
+        if let Some(&target) = target {
+            graph.add_loop(cell, target);
+        }
+    }
+}
+
+fn is_dead_end(graph: &MazeGraph, cell: usize) -> bool {
+    graph
+        .neighbours(cell)
+        .filter(|(_, neighbour)| graph.is_open(graph.edge(cell, *neighbour)))
+        .count()
+        == 1
+}
+
+/// Neighbour of a cell in the row above it, whichever side they share
+fn above(graph: &MazeGraph, cell: usize) -> Option<usize> {
+    let (_, y) = graph.coords(cell);
+    graph
+        .neighbours(cell)
+        .map(|(_, neighbour)| neighbour)
+        .find(|neighbour| graph.coords(*neighbour).1 == y + 1)
+}
+
+/// A random cell to start from, followed by every cell so each region gets visited
+fn random_starts(graph: &MazeGraph, rng: &mut StdRng) -> Vec<usize> {
+    let cells: Vec<usize> = graph.cells().collect();
+    let first = *cells.choose(rng).expect("maze without cells");
+    once(first).chain(cells).collect()
+}
+
+/// Adds random edges between the trees of `edges` until every region is one tree
+fn complete_forest(
+    graph: &MazeGraph,
+    edges: Vec<(usize, usize)>,
+    rng: &mut StdRng,
+) -> Vec<(usize, usize)> {
+    let mut sets = DisjointSets::new(graph.cell_count());
+    for &(a, b) in &edges {
+        sets.union(a, b);
+    }
+
+    let mut candidates: Vec<(usize, usize)> = graph
+        .cells()
+        .flat_map(|cell| {
+            graph
+                .neighbours(cell)
+                .filter(move |(_, neighbour)| cell < *neighbour)
+                .map(move |(_, neighbour)| (cell, neighbour))
+        })
+        .collect();
+    candidates.shuffle(rng);
+
+    edges
+        .into_iter()
+        .chain(candidates.into_iter().filter(|(a, b)| sets.union(*a, *b)))
+        .collect()
+}
+
+struct DisjointSets {
+    parents: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(size: usize) -> Self {
+        Self {
+            parents: (0..size).collect(),
+        }
+    }
+
+    fn find(&mut self, mut cell: usize) -> usize {
+        while self.parents[cell] != cell {
+            self.parents[cell] = self.parents[self.parents[cell]];
+            cell = self.parents[cell];
+        }
+        cell
+    }
+
+    /// Joins the sets of `a` and `b`, returns false when they already were one set
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        self.parents[a] = b;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::maze_topology::Topology;
+
+    /// Whether `edges` connect every cell of `graph` without any cycle
+    fn is_spanning_tree(graph: &MazeGraph, edges: &[(usize, usize)]) -> bool {
+        let cells: Vec<usize> = graph.cells().collect();
+        if edges.len() + 1 != cells.len() {
+            return false;
+        }
+
+        let mut adjacency = vec![Vec::new(); graph.cell_count()];
+        for &(a, b) in edges {
+            if !graph.neighbours(a).any(|(_, neighbour)| neighbour == b) {
+                return false;
+            }
+            adjacency[a].push(b);
+            adjacency[b].push(a);
+        }
+
+        let mut visited = vec![false; graph.cell_count()];
+        visited[cells[0]] = true;
+        let mut queue = VecDeque::from([cells[0]]);
+        while let Some(cell) = queue.pop_front() {
+            for &next in &adjacency[cell] {
+                if !visited[next] {
+                    visited[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        cells.iter().all(|cell| visited[*cell])
+    }
+
+    #[test]
+    fn every_generator_yields_a_spanning_tree() {
+        let graph = MazeGraph::new(7, 5, Topology::Square, vec![true; 35]);
+
+        for algorithm in MazeAlgorithm::ALL {
+            for seed in 0..10 {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let edges = algorithm.generator().generate(&graph, &mut rng);
+                assert!(
+                    is_spanning_tree(&graph, &edges),
+                    "{} with seed {} did not yield a spanning tree",
+                    algorithm.name(),
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn generators_are_reproducible() {
+        let graph = MazeGraph::new(7, 5, Topology::Square, vec![true; 35]);
+
+        for algorithm in MazeAlgorithm::ALL {
+            let first = algorithm
+                .generator()
+                .generate(&graph, &mut StdRng::seed_from_u64(42));
+            let second = algorithm
+                .generator()
+                .generate(&graph, &mut StdRng::seed_from_u64(42));
+            assert_eq!(first, second, "{} is not reproducible", algorithm.name());
+        }
+    }
+
+    #[test]
+    fn braid_keeps_the_tree_intact() {
+        let mut graph = MazeGraph::new(7, 5, Topology::Square, vec![true; 35]);
+        let mut rng = StdRng::seed_from_u64(7);
+        let edges = RecursiveBacktracker.generate(&graph, &mut rng);
+        graph.set_tree(&edges, graph.root());
+        let parents: Vec<Option<usize>> = graph.cells().map(|cell| graph.parent(cell)).collect();
+        let root = graph.root();
+
+        braid(&mut graph, 1., &mut rng);
+
+        assert_eq!(graph.root(), root);
+        assert_eq!(
+            graph
+                .cells()
+                .map(|cell| graph.parent(cell))
+                .collect::<Vec<_>>(),
+            parents
+        );
+        assert!(graph.loops().count() > 0);
+        assert!(graph.cells().all(|cell| !is_dead_end(&graph, cell)));
+    }
+
+    #[test]
+    fn braid_without_ratio_adds_no_loops() {
+        let mut graph = MazeGraph::new(7, 5, Topology::Square, vec![true; 35]);
+        let mut rng = StdRng::seed_from_u64(7);
+        let edges = Prim.generate(&graph, &mut rng);
+        graph.set_tree(&edges, graph.root());
+
+        braid(&mut graph, 0., &mut rng);
+
+        assert_eq!(graph.loops().count(), 0);
+    }
+}
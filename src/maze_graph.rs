@@ -0,0 +1,499 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    iter::once,
+};
+
+use bevy::math::{IVec2, Vec2};
+
+use crate::maze_topology::Topology;
+
+/// Side of a cell, the diagonal ones only exist on hexagonal grids
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+impl Direction {
+    /// Unit vector pointing in this direction on screen
+    pub fn vector(self) -> Vec2 {
+        match self {
+            Direction::Up => Vec2::Y,
+            Direction::Down => Vec2::NEG_Y,
+            Direction::Left => Vec2::NEG_X,
+            Direction::Right => Vec2::X,
+            Direction::UpLeft => Vec2::new(-1., 1.).normalize(),
+            Direction::UpRight => Vec2::new(1., 1.).normalize(),
+            Direction::DownLeft => Vec2::new(-1., -1.).normalize(),
+            Direction::DownRight => Vec2::new(1., -1.).normalize(),
+        }
+    }
+}
+
+/// Passage between a cell and its neighbour, always stored from the cell with
+/// the lowest index so every edge has exactly one representation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Edge {
+    pub cell: usize,
+    pub direction: Direction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeChange {
+    Opened(Edge),
+    Closed(Edge),
+}
+
+/// Plain data representation of the maze.
+///
+/// Cells are stored row by row and addressed by their index `y * width + x`.
+/// Every cell except the root points to its parent, together forming the tree
+/// the origin-shift algorithm mutates. An edge between two neighbouring cells
+/// is open when one of them is the parent of the other.
+///
+/// Disabled cells are not part of the maze: they have no neighbours and never
+/// get a parent. When the mask splits the grid in separate regions, every
+/// region keeps its own fixed tree and only the region of the root shifts.
+#[derive(Debug, Clone)]
+pub struct MazeGraph {
+    width: usize,
+    height: usize,
+    topology: Topology,
+    enabled: Vec<bool>,
+    parents: Vec<Option<usize>>,
+    root: usize,
+    /// Passages kept open on top of the tree, turning the maze into a braid
+    loops: HashSet<Edge>,
+}
+
+impl MazeGraph {
+    /// Creates a graph without any open edges, `enabled` tells which cells are part of the maze
+    pub fn new(width: usize, height: usize, topology: Topology, enabled: Vec<bool>) -> Self {
+        assert_eq!(
+            enabled.len(),
+            width * height,
+            "mask does not match the maze"
+        );
+        let root = enabled
+            .iter()
+            .rposition(|enabled| *enabled)
+            .expect("maze needs at least one enabled cell");
+
+        Self {
+            width,
+            height,
+            topology,
+            enabled,
+            parents: vec![None; width * height],
+            root,
+            loops: HashSet::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.parents.len()
+    }
+
+    pub fn is_enabled(&self, cell: usize) -> bool {
+        self.enabled[cell]
+    }
+
+    /// All cells that are part of the maze
+    pub fn cells(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.cell_count()).filter(|cell| self.enabled[*cell])
+    }
+
+    pub fn index(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
+    }
+
+    pub fn coords(&self, cell: usize) -> (usize, usize) {
+        (cell % self.width, cell / self.width)
+    }
+
+    /// Sides of the cell, whether or not there is a cell on the other side
+    pub fn directions(&self, cell: usize) -> &'static [Direction] {
+        let (x, y) = self.coords(cell);
+        self.topology.directions(IVec2::new(x as i32, y as i32))
+    }
+
+    /// Neighbouring cell in `direction`, if it exists and is enabled
+    pub fn neighbour(&self, cell: usize, direction: Direction) -> Option<usize> {
+        let (x, y) = self.coords(cell);
+        let target = self
+            .topology
+            .neighbour(IVec2::new(x as i32, y as i32), direction)?;
+
+        if !self.enabled[cell]
+            || target.x < 0
+            || target.y < 0
+            || target.x >= self.width as i32
+            || target.y >= self.height as i32
+        {
+            return None;
+        }
+
+        let target = self.index(target.x as usize, target.y as usize);
+        self.enabled[target].then_some(target)
+    }
+
+    pub fn neighbours(&self, cell: usize) -> impl Iterator<Item = (Direction, usize)> + '_ {
+        self.directions(cell)
+            .iter()
+            .filter_map(move |direction| Some((*direction, self.neighbour(cell, *direction)?)))
+    }
+
+    /// Neighbours reachable without going through a wall
+    pub fn open_neighbours(&self, cell: usize) -> impl Iterator<Item = usize> + '_ {
+        self.neighbours(cell)
+            .map(|(_, neighbour)| neighbour)
+            .filter(move |neighbour| self.is_open(self.edge(cell, *neighbour)))
+    }
+
+    /// Edge between two neighbouring cells
+    pub fn edge(&self, a: usize, b: usize) -> Edge {
+        let (cell, other) = (a.min(b), a.max(b));
+        let direction = self
+            .neighbours(cell)
+            .find(|(_, neighbour)| *neighbour == other)
+            .map(|(direction, _)| direction)
+            .expect("edge between cells that are not neighbours");
+
+        Edge { cell, direction }
+    }
+
+    pub fn root(&self) -> usize {
+        self.root
+    }
+
+    pub fn set_root(&mut self, cell: usize) {
+        self.parents[cell] = None;
+        self.root = cell;
+    }
+
+    pub fn parent(&self, cell: usize) -> Option<usize> {
+        self.parents[cell]
+    }
+
+    pub fn has_edge(&self, a: usize, b: usize) -> bool {
+        self.parents[a] == Some(b) || self.parents[b] == Some(a)
+    }
+
+    pub fn is_open(&self, edge: Edge) -> bool {
+        self.neighbour(edge.cell, edge.direction)
+            .is_some_and(|neighbour| {
+                self.has_edge(edge.cell, neighbour) || self.loops.contains(&edge)
+            })
+    }
+
+    /// Passages open on top of the tree
+    pub fn loops(&self) -> impl Iterator<Item = Edge> + '_ {
+        self.loops.iter().copied()
+    }
+
+    /// Opens the passage between two neighbouring cells for good, whatever the tree does
+    pub fn add_loop(&mut self, a: usize, b: usize) {
+        let edge = self.edge(a, b);
+        self.loops.insert(edge);
+    }
+
+    /// Amount of steps from `start` to every cell when walls are ignored,
+    /// `None` for cells in another region
+    pub fn distances(&self, start: usize) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.cell_count()];
+        distances[start] = Some(0);
+
+        let mut queue = VecDeque::from([start]);
+        while let Some(cell) = queue.pop_front() {
+            let distance = distances[cell].map(|distance| distance + 1);
+            for (_, next) in self.neighbours(cell) {
+                if distances[next].is_none() {
+                    distances[next] = distance;
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        distances
+    }
+
+    /// Cells on the way from `from` to `to` along the tree, both included,
+    /// `None` when they are in different regions
+    pub fn tree_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let ancestors = |cell: usize| std::iter::successors(Some(cell), |cell| self.parents[*cell]);
+
+        let up: Vec<usize> = ancestors(from).collect();
+        let (down_len, meet) = ancestors(to)
+            .enumerate()
+            .find_map(|(steps, cell)| Some((steps, up.iter().position(|up| *up == cell)?)))?;
+
+        let mut path = up[..=meet].to_vec();
+        let down: Vec<usize> = ancestors(to).take(down_len).collect();
+        path.extend(down.into_iter().rev());
+        Some(path)
+    }
+
+    /// Groups the enabled cells into regions connected through neighbours
+    pub fn components(&self) -> Vec<Vec<usize>> {
+        let mut visited = vec![false; self.cell_count()];
+        let mut components = Vec::new();
+
+        for start in self.cells() {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+
+            let mut component = vec![start];
+            let mut queue = VecDeque::from([start]);
+            while let Some(cell) = queue.pop_front() {
+                for (_, next) in self.neighbours(cell) {
+                    if !visited[next] {
+                        visited[next] = true;
+                        component.push(next);
+                        queue.push_back(next);
+                    }
+                }
+            }
+            components.push(component);
+        }
+
+        components
+    }
+
+    /// Replaces the tree with the given undirected edges, oriented towards `root`.
+    /// Regions the edges do not connect to `root` are rooted in their first cell.
+    pub fn set_tree(&mut self, edges: &[(usize, usize)], root: usize) {
+        let mut adjacency = vec![Vec::new(); self.cell_count()];
+        for &(a, b) in edges {
+            adjacency[a].push(b);
+            adjacency[b].push(a);
+        }
+
+        self.parents.fill(None);
+        self.root = root;
+        self.loops.clear();
+
+        let mut visited = vec![false; self.cell_count()];
+        let starts: Vec<usize> = once(root).chain(self.cells()).collect();
+
+        for start in starts {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+
+            let mut queue = VecDeque::from([start]);
+            while let Some(cell) = queue.pop_front() {
+                for &next in &adjacency[cell] {
+                    if !visited[next] {
+                        visited[next] = true;
+                        self.parents[next] = Some(cell);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Performs one origin-shift step: the root points to its neighbour in
+    /// `direction`, which then becomes the new root. Returns the edges that
+    /// got opened or closed by the step.
+    pub fn shift_root(&mut self, direction: Direction) -> Vec<EdgeChange> {
+        let old_root = self.root;
+        let Some(new_root) = self.neighbour(old_root, direction) else {
+            return Vec::new();
+        };
+        let old_parent = self.parents[new_root];
+
+        self.parents[old_root] = Some(new_root);
+        self.set_root(new_root);
+
+        // stepping back onto the previous root keeps the same passage open
+        if old_parent == Some(old_root) {
+            return Vec::new();
+        }
+
+        // loops stay open, so the tree moving through them changes nothing
+        let mut changes = vec![EdgeChange::Opened(self.edge(old_root, new_root))];
+        if let Some(old_parent) = old_parent {
+            changes.push(EdgeChange::Closed(self.edge(new_root, old_parent)));
+        }
+        changes.retain(|(EdgeChange::Opened(edge) | EdgeChange::Closed(edge))| {
+            !self.loops.contains(edge)
+        });
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(width: usize, height: usize) -> MazeGraph {
+        MazeGraph::new(width, height, Topology::Square, vec![true; width * height])
+    }
+
+    #[test]
+    fn cells_are_addressed_row_by_row() {
+        let graph = grid(3, 2);
+
+        assert_eq!(graph.index(2, 1), 5);
+        assert_eq!(graph.coords(5), (2, 1));
+        assert_eq!(graph.coords(graph.index(1, 0)), (1, 0));
+    }
+
+    #[test]
+    fn neighbours_stay_inside_the_grid() {
+        let graph = grid(3, 2);
+
+        assert_eq!(graph.neighbour(0, Direction::Right), Some(1));
+        assert_eq!(graph.neighbour(0, Direction::Up), Some(3));
+        assert_eq!(graph.neighbour(0, Direction::Left), None);
+        assert_eq!(graph.neighbour(0, Direction::Down), None);
+        assert_eq!(graph.neighbour(5, Direction::Up), None);
+        assert_eq!(graph.neighbour(0, Direction::UpRight), None);
+    }
+
+    #[test]
+    fn disabled_cells_have_no_neighbours() {
+        let graph = MazeGraph::new(3, 1, Topology::Square, vec![true, false, true]);
+
+        assert_eq!(graph.neighbour(0, Direction::Right), None);
+        assert_eq!(graph.neighbour(1, Direction::Left), None);
+        assert_eq!(graph.cells().collect::<Vec<_>>(), vec![0, 2]);
+    }
+
+    #[test]
+    fn set_tree_orients_the_edges_towards_the_root() {
+        let mut graph = grid(2, 2);
+        graph.set_tree(&[(0, 1), (0, 2), (2, 3)], 3);
+
+        assert_eq!(graph.root(), 3);
+        assert_eq!(graph.parent(3), None);
+        assert_eq!(graph.parent(2), Some(3));
+        assert_eq!(graph.parent(0), Some(2));
+        assert_eq!(graph.parent(1), Some(0));
+        assert!(graph.is_open(graph.edge(0, 1)));
+        assert!(!graph.is_open(graph.edge(1, 3)));
+    }
+
+    #[test]
+    fn set_tree_roots_unreached_regions_in_their_first_cell() {
+        let mut graph = MazeGraph::new(3, 1, Topology::Square, vec![true, false, true]);
+        graph.set_tree(&[], 2);
+
+        assert_eq!(graph.root(), 2);
+        assert_eq!(graph.parent(0), None);
+        assert_eq!(graph.parent(2), None);
+    }
+
+    #[test]
+    fn shift_root_reports_the_opened_and_closed_edges() {
+        let mut graph = grid(2, 2);
+        graph.set_tree(&[(0, 1), (0, 2), (2, 3)], 1);
+
+        let changes = graph.shift_root(Direction::Up);
+
+        assert_eq!(
+            changes,
+            vec![
+                EdgeChange::Opened(graph.edge(1, 3)),
+                EdgeChange::Closed(graph.edge(2, 3)),
+            ]
+        );
+        assert_eq!(graph.root(), 3);
+        assert_eq!(graph.parent(1), Some(3));
+        assert!(graph.is_open(graph.edge(1, 3)));
+        assert!(!graph.is_open(graph.edge(2, 3)));
+    }
+
+    #[test]
+    fn shift_root_back_onto_the_previous_root_changes_nothing() {
+        let mut graph = grid(2, 1);
+        graph.set_tree(&[(0, 1)], 1);
+
+        assert!(graph.shift_root(Direction::Left).is_empty());
+        assert_eq!(graph.root(), 0);
+        assert!(graph.is_open(graph.edge(0, 1)));
+    }
+
+    #[test]
+    fn shift_root_off_the_grid_does_nothing() {
+        let mut graph = grid(2, 1);
+        graph.set_tree(&[(0, 1)], 1);
+
+        assert!(graph.shift_root(Direction::Right).is_empty());
+        assert_eq!(graph.root(), 1);
+    }
+
+    #[test]
+    fn shift_root_keeps_loops_open() {
+        let mut graph = grid(2, 2);
+        graph.set_tree(&[(0, 1), (0, 2), (2, 3)], 1);
+        graph.add_loop(2, 3);
+
+        let changes = graph.shift_root(Direction::Up);
+
+        assert_eq!(changes, vec![EdgeChange::Opened(graph.edge(1, 3))]);
+        assert!(graph.is_open(graph.edge(2, 3)));
+    }
+
+    #[test]
+    fn distances_ignore_walls_but_not_regions() {
+        let graph = grid(3, 3);
+        let distances = graph.distances(0);
+
+        assert_eq!(distances[0], Some(0));
+        assert_eq!(distances[4], Some(2));
+        assert_eq!(distances[8], Some(4));
+
+        let split = MazeGraph::new(3, 1, Topology::Square, vec![true, false, true]);
+        assert_eq!(split.distances(0), vec![Some(0), None, None]);
+    }
+
+    #[test]
+    fn components_split_on_disabled_cells() {
+        assert_eq!(grid(3, 1).components(), vec![vec![0, 1, 2]]);
+
+        let split = MazeGraph::new(3, 1, Topology::Square, vec![true, false, true]);
+        assert_eq!(split.components(), vec![vec![0], vec![2]]);
+    }
+
+    #[test]
+    fn tree_path_goes_through_the_common_ancestor() {
+        let mut graph = grid(2, 2);
+        graph.set_tree(&[(0, 1), (0, 2), (2, 3)], 0);
+
+        assert_eq!(graph.tree_path(1, 3), Some(vec![1, 0, 2, 3]));
+        assert_eq!(graph.tree_path(3, 1), Some(vec![3, 2, 0, 1]));
+        assert_eq!(graph.tree_path(3, 0), Some(vec![3, 2, 0]));
+        assert_eq!(graph.tree_path(2, 2), Some(vec![2]));
+    }
+
+    #[test]
+    fn tree_path_does_not_cross_regions() {
+        let mut graph = MazeGraph::new(3, 1, Topology::Square, vec![true, false, true]);
+        graph.set_tree(&[], 2);
+
+        assert_eq!(graph.tree_path(0, 2), None);
+    }
+}
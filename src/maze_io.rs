@@ -0,0 +1,481 @@
+use std::{fs, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    maze::{Maze, Stairs},
+    maze_graph::{Direction, Edge, MazeGraph},
+    maze_specs::MazeSeed,
+    maze_topology::Topology,
+};
+
+pub struct MazeIoPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for MazeIoPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, report_import_error);
+        app.add_systems(Update, export_maze.run_if(in_state(self.state.clone())));
+    }
+}
+
+/// Floors and stairs of a maze, without anything that lives in the world
+#[derive(Debug, Clone)]
+pub struct MazeLayout {
+    pub floors: Vec<MazeGraph>,
+    pub stairs: Vec<Stairs>,
+}
+
+/// Layout loaded from a file, used instead of generating a maze
+#[derive(Resource, Default)]
+pub struct MazeImport {
+    pub layout: Option<MazeLayout>,
+    /// Why the file could not be loaded, reported once the app runs
+    pub error: Option<String>,
+}
+
+impl MazeImport {
+    pub fn load(path: &str) -> Self {
+        match load_file(path.as_ref()) {
+            Ok(layout) => Self {
+                layout: Some(layout),
+                error: None,
+            },
+            Err(error) => Self {
+                layout: None,
+                error: Some(format!("{}: {}", path, error)),
+            },
+        }
+    }
+}
+
+impl MazeLayout {
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(
+            self.floors[0].width() as f32,
+            self.floors[0].height() as f32,
+        )
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.floors[0].topology()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct MazeFile {
+    width: usize,
+    height: usize,
+    topology: Topology,
+    floors: Vec<FloorFile>,
+    stairs: Vec<Stairs>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FloorFile {
+    root: usize,
+    enabled: Vec<bool>,
+    parents: Vec<Option<usize>>,
+    loops: Vec<(usize, usize)>,
+}
+
+/// Reads a layout from a JSON file, or an ASCII one for any other extension
+pub fn load_file(path: &Path) -> Result<MazeLayout, String> {
+    let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        from_json(&contents)
+    } else {
+        from_ascii(&contents)
+    }
+}
+
+pub fn to_json(layout: &MazeLayout) -> String {
+    let first = &layout.floors[0];
+    let file = MazeFile {
+        width: first.width(),
+        height: first.height(),
+        topology: first.topology(),
+        floors: layout
+            .floors
+            .iter()
+            .map(|graph| FloorFile {
+                root: graph.root(),
+                enabled: (0..graph.cell_count())
+                    .map(|cell| graph.is_enabled(cell))
+                    .collect(),
+                parents: (0..graph.cell_count())
+                    .map(|cell| graph.parent(cell))
+                    .collect(),
+                loops: graph
+                    .loops()
+                    .filter_map(|edge| {
+                        Some((edge.cell, graph.neighbour(edge.cell, edge.direction)?))
+                    })
+                    .collect(),
+            })
+            .collect(),
+        stairs: layout.stairs.clone(),
+    };
+
+    serde_json::to_string_pretty(&file).expect("maze layouts always serialize")
+}
+
+pub fn from_json(contents: &str) -> Result<MazeLayout, String> {
+    let file: MazeFile = serde_json::from_str(contents).map_err(|error| error.to_string())?;
+    let cell_count = file.width * file.height;
+
+    let floors = file
+        .floors
+        .into_iter()
+        .map(|floor| {
+            if floor.enabled.len() != cell_count || floor.parents.len() != cell_count {
+                return Err(format!("floor does not have {} cells", cell_count));
+            }
+            if !floor.enabled.contains(&true) {
+                return Err("floor without enabled cells".to_string());
+            }
+
+            let mut graph = MazeGraph::new(file.width, file.height, file.topology, floor.enabled);
+            let edges: Vec<(usize, usize)> = floor
+                .parents
+                .iter()
+                .enumerate()
+                .filter_map(|(cell, parent)| Some((cell, (*parent)?)))
+                .collect();
+
+            // the graph panics on edges between cells that do not touch
+            for &(a, b) in edges.iter().chain(&floor.loops) {
+                if a >= cell_count || b >= cell_count || !are_neighbours(&graph, a, b) {
+                    return Err(format!("cells {} and {} are not neighbours", a, b));
+                }
+            }
+            if floor.root >= cell_count || !graph.is_enabled(floor.root) {
+                return Err(format!("root {} is not an enabled cell", floor.root));
+            }
+
+            graph.set_tree(&edges, floor.root);
+            for &(a, b) in &floor.loops {
+                graph.add_loop(a, b);
+            }
+            Ok(graph)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    layout(floors, file.stairs)
+}
+
+/// Draws every floor as a grid of walls, floors being separated by an empty line.
+///
+/// Each cell is two characters wide: `@` marks the root, `S` stairs to the floor
+/// above and `##` a disabled cell. Only square grids can be drawn this way.
+pub fn to_ascii(layout: &MazeLayout) -> Result<String, String> {
+    if layout.topology() != Topology::Square {
+        return Err("only square mazes can be written as ASCII".to_string());
+    }
+
+    let floors: Vec<String> = layout
+        .floors
+        .iter()
+        .enumerate()
+        .map(|(floor, graph)| {
+            let mut lines = Vec::new();
+
+            for y in (0..graph.height()).rev() {
+                let mut walls = String::from("+");
+                let mut cells = String::from("|");
+
+                for x in 0..graph.width() {
+                    let cell = graph.index(x, y);
+                    walls += if is_open(graph, cell, Direction::Up) {
+                        "  +"
+                    } else {
+                        "--+"
+                    };
+
+                    if !graph.is_enabled(cell) {
+                        cells += "##";
+                    } else {
+                        cells.push(if cell == graph.root() { '@' } else { ' ' });
+                        let stairs = layout
+                            .stairs
+                            .iter()
+                            .any(|stairs| stairs.floor == floor && stairs.cell == cell);
+                        cells.push(if stairs { 'S' } else { ' ' });
+                    }
+                    cells.push(if is_open(graph, cell, Direction::Right) {
+                        ' '
+                    } else {
+                        '|'
+                    });
+                }
+
+                lines.push(walls);
+                lines.push(cells);
+            }
+            lines.push("+--".repeat(graph.width()) + "+");
+
+            lines.join("\n")
+        })
+        .collect();
+
+    Ok(floors.join("\n\n") + "\n")
+}
+
+pub fn from_ascii(contents: &str) -> Result<MazeLayout, String> {
+    let mut floors = Vec::new();
+    let mut stairs = Vec::new();
+
+    let mut blocks: Vec<Vec<&[u8]>> = vec![Vec::new()];
+    for line in contents.lines() {
+        if line.trim().is_empty() {
+            blocks.push(Vec::new());
+        } else if let Some(block) = blocks.last_mut() {
+            block.push(line.as_bytes());
+        }
+    }
+    blocks.retain(|lines| !lines.is_empty());
+
+    for (floor, lines) in blocks.into_iter().enumerate() {
+        if lines.len() < 3 || lines.len() % 2 == 0 {
+            return Err(format!("floor {} has an incomplete row", floor));
+        }
+        let (width, height) = (lines[0].len().saturating_sub(1) / 3, lines.len() / 2);
+        if width == 0 {
+            return Err(format!("floor {} has no columns", floor));
+        }
+        // a missing character would read as an open passage
+        let line_length = 3 * width + 1;
+        if let Some(line) = lines.iter().position(|line| line.len() < line_length) {
+            return Err(format!(
+                "line {} of floor {} is shorter than {} characters",
+                line + 1,
+                floor,
+                line_length
+            ));
+        }
+        let at = |line: usize, column: usize| lines[line][column];
+
+        // the drawing starts with the top row, the grid with the bottom one
+        let line_of = |y: usize| 2 * (height - 1 - y) + 1;
+        let mut enabled = vec![true; width * height];
+        let mut root = None;
+
+        for y in 0..height {
+            for x in 0..width {
+                let cell = y * width + x;
+                let content = (at(line_of(y), 3 * x + 1), at(line_of(y), 3 * x + 2));
+                match content {
+                    (b'#', b'#') => enabled[cell] = false,
+                    (marker, stairs_marker) => {
+                        if marker == b'@' {
+                            root = Some(cell);
+                        }
+                        if stairs_marker == b'S' {
+                            stairs.push(Stairs { floor, cell });
+                        }
+                    }
+                }
+            }
+        }
+
+        if !enabled.contains(&true) {
+            return Err(format!("floor {} has no enabled cells", floor));
+        }
+        let mut graph = MazeGraph::new(width, height, Topology::Square, enabled);
+        let root = root.unwrap_or(graph.root());
+
+        let mut edges = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let cell = y * width + x;
+                let open_right = at(line_of(y), 3 * x + 3) == b' ';
+                let open_up = at(line_of(y) - 1, 3 * x + 1) == b' ';
+
+                for (open, direction) in [(open_right, Direction::Right), (open_up, Direction::Up)]
+                {
+                    if let Some(neighbour) = graph.neighbour(cell, direction).filter(|_| open) {
+                        edges.push((cell, neighbour));
+                    }
+                }
+            }
+        }
+
+        // passages the tree does not need are loops
+        graph.set_tree(&edges, root);
+        for &(a, b) in &edges {
+            if !graph.has_edge(a, b) {
+                graph.add_loop(a, b);
+            }
+        }
+        floors.push(graph);
+    }
+
+    layout(floors, stairs)
+}
+
+fn layout(floors: Vec<MazeGraph>, stairs: Vec<Stairs>) -> Result<MazeLayout, String> {
+    let Some(first) = floors.first() else {
+        return Err("maze without floors".to_string());
+    };
+    if floors
+        .iter()
+        .any(|graph| graph.width() != first.width() || graph.height() != first.height())
+    {
+        return Err("floors of different sizes".to_string());
+    }
+    if let Some(stairs) = stairs.iter().find(|stairs| {
+        stairs.floor + 1 >= floors.len()
+            || stairs.cell >= first.cell_count()
+            || !floors[stairs.floor].is_enabled(stairs.cell)
+            || !floors[stairs.floor + 1].is_enabled(stairs.cell)
+    }) {
+        return Err(format!("stairs {:?} do not connect two floors", stairs));
+    }
+
+    Ok(MazeLayout { floors, stairs })
+}
+
+fn are_neighbours(graph: &MazeGraph, a: usize, b: usize) -> bool {
+    graph.neighbours(a).any(|(_, neighbour)| neighbour == b)
+}
+
+fn is_open(graph: &MazeGraph, cell: usize, direction: Direction) -> bool {
+    graph.is_open(Edge { cell, direction })
+}
+
+fn report_import_error(import: Res<MazeImport>) {
+    if let Some(error) = &import.error {
+        warn!("Could not load maze {}", error);
+    }
+}
+
+// Press F5 to write the current maze next to the game, to attach to bug reports
+fn export_maze(keys: Res<ButtonInput<KeyCode>>, maze: Res<Maze>, seed: Res<MazeSeed>) {
+    if !keys.just_pressed(KeyCode::F5) {
+        return;
+    }
+
+    let layout = MazeLayout {
+        floors: maze.floors.clone(),
+        stairs: maze.stairs.clone(),
+    };
+    let name = format!("maze-{}", seed.0);
+
+    let files = [
+        (format!("{}.json", name), Ok(to_json(&layout))),
+        (format!("{}.txt", name), to_ascii(&layout)),
+    ];
+
+    for (path, contents) in files {
+        match contents.and_then(|contents| fs::write(&path, contents).map_err(|e| e.to_string())) {
+            Ok(()) => info!("Exported maze to {}", path),
+            Err(error) => warn!("Could not export maze to {}: {}", path, error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::maze_generators::{braid, MazeGenerator, RecursiveBacktracker};
+
+    fn sample_layout() -> MazeLayout {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut enabled = vec![true; 5 * 4];
+        enabled[7] = false;
+
+        let floors = (0..2)
+            .map(|_| {
+                let mut graph = MazeGraph::new(5, 4, Topology::Square, enabled.clone());
+                let edges = RecursiveBacktracker.generate(&graph, &mut rng);
+                graph.set_tree(&edges, 2);
+                braid(&mut graph, 0.5, &mut rng);
+                graph
+            })
+            .collect();
+
+        MazeLayout {
+            floors,
+            stairs: vec![Stairs { floor: 0, cell: 4 }, Stairs { floor: 0, cell: 15 }],
+        }
+    }
+
+    fn open_edges(graph: &MazeGraph) -> HashSet<Edge> {
+        graph
+            .cells()
+            .flat_map(|cell| graph.open_neighbours(cell).map(move |next| (cell, next)))
+            .map(|(cell, next)| graph.edge(cell, next))
+            .collect()
+    }
+
+    fn stairs(layout: &MazeLayout) -> Vec<(usize, usize)> {
+        layout
+            .stairs
+            .iter()
+            .map(|stairs| (stairs.floor, stairs.cell))
+            .collect()
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let layout = sample_layout();
+        let loaded = from_json(&to_json(&layout)).unwrap();
+
+        assert_eq!(stairs(&loaded), stairs(&layout));
+        for (graph, original) in loaded.floors.iter().zip(&layout.floors) {
+            assert_eq!(graph.root(), original.root());
+            for cell in 0..original.cell_count() {
+                assert_eq!(graph.is_enabled(cell), original.is_enabled(cell));
+                assert_eq!(graph.parent(cell), original.parent(cell));
+            }
+            assert_eq!(
+                graph.loops().collect::<HashSet<_>>(),
+                original.loops().collect::<HashSet<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn ascii_round_trips() {
+        let layout = sample_layout();
+        let loaded = from_ascii(&to_ascii(&layout).unwrap()).unwrap();
+
+        assert_eq!(stairs(&loaded), stairs(&layout));
+        for (graph, original) in loaded.floors.iter().zip(&layout.floors) {
+            assert_eq!(graph.root(), original.root());
+            assert_eq!(
+                graph.cells().collect::<Vec<_>>(),
+                original.cells().collect::<Vec<_>>()
+            );
+            assert_eq!(open_edges(graph), open_edges(original));
+        }
+    }
+
+    #[test]
+    fn ascii_rejects_truncated_lines() {
+        let ascii = to_ascii(&sample_layout()).unwrap();
+        let mut lines: Vec<&str> = ascii.lines().collect();
+        lines[1] = &lines[1][..lines[1].len() - 4];
+
+        assert!(from_ascii(&lines.join("\n")).is_err());
+    }
+
+    #[test]
+    fn ascii_only_draws_square_mazes() {
+        let mut layout = sample_layout();
+        layout.floors = vec![MazeGraph::new(3, 3, Topology::Hex, vec![true; 9])];
+        layout.stairs.clear();
+
+        assert!(to_ascii(&layout).is_err());
+    }
+}
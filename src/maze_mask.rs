@@ -0,0 +1,290 @@
+use std::{env, path::PathBuf};
+
+use bevy::{
+    asset::RenderAssetUsages,
+    image::{CompressedImageFormats, ImageSampler, ImageType},
+    prelude::*,
+};
+
+/// Which cells of the `MazeShape` rectangle are part of the maze
+#[derive(Resource, Debug, Default, Clone, PartialEq)]
+pub enum MazeMask {
+    #[default]
+    Full,
+    /// Ellipse touching the sides of the grid
+    Circle,
+    /// Circle with a hole, `inner` being the hole radius relative to the outer radius
+    Ring { inner: f32 },
+    /// Text written in a blocky font, stretched over the grid
+    Text(String),
+    /// Image from the assets folder, bright opaque pixels are part of the maze
+    Image(String),
+}
+
+impl MazeMask {
+    pub fn presets() -> [MazeMask; 5] {
+        [
+            MazeMask::Full,
+            MazeMask::Circle,
+            MazeMask::Ring { inner: 0.45 },
+            MazeMask::Text("A".to_string()),
+            MazeMask::Image("masks/keyhole.png".to_string()),
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MazeMask::Full => "Full",
+            MazeMask::Circle => "Circle",
+            MazeMask::Ring { .. } => "Ring",
+            MazeMask::Text(_) => "Text",
+            MazeMask::Image(_) => "Image",
+        }
+    }
+
+    pub fn next(&self) -> MazeMask {
+        let presets = Self::presets();
+        let index = presets
+            .iter()
+            .position(|preset| preset.name() == self.name())
+            .unwrap_or(0);
+        presets[(index + 1) % presets.len()].clone()
+    }
+
+    /// Rasterizes the mask onto the grid, indexed the same way as the cells of a `MazeGraph`
+    pub fn cells(&self, width: usize, height: usize) -> Vec<bool> {
+        let mut enabled = vec![true; width * height];
+
+        for y in 0..height {
+            for x in 0..width {
+                // position relative to the grid, from -1 to 1 on both axes
+                let relative = Vec2::new(
+                    (x as f32 + 0.5) / width as f32 * 2. - 1.,
+                    (y as f32 + 0.5) / height as f32 * 2. - 1.,
+                );
+
+                enabled[y * width + x] = match self {
+                    MazeMask::Full => true,
+                    MazeMask::Circle => relative.length() <= 1.,
+                    MazeMask::Ring { inner } => {
+                        relative.length() <= 1. && relative.length() >= *inner
+                    }
+                    MazeMask::Text(_) | MazeMask::Image(_) => true,
+                };
+            }
+        }
+
+        let bitmap = match self {
+            MazeMask::Text(text) => Some(text_bitmap(text)),
+            MazeMask::Image(path) => image_bitmap(path),
+            _ => None,
+        };
+
+        // stretch the bitmap over the grid, its first row being the top of the maze
+        if let Some(bitmap) = bitmap.filter(|bitmap| bitmap.iter().all(|row| !row.is_empty())) {
+            let (bitmap_width, bitmap_height) = (bitmap[0].len(), bitmap.len());
+            for y in 0..height {
+                for x in 0..width {
+                    let bitmap_x = x * bitmap_width / width;
+                    let bitmap_y = (height - 1 - y) * bitmap_height / height;
+                    enabled[y * width + x] = bitmap[bitmap_y][bitmap_x];
+                }
+            }
+        }
+
+        // never hand out a maze without cells
+        if !enabled.contains(&true) {
+            warn!(
+                "Maze mask {:?} disables every cell, using the full grid",
+                self
+            );
+            enabled.fill(true);
+        }
+
+        enabled
+    }
+}
+
+fn image_bitmap(path: &str) -> Option<Vec<Vec<bool>>> {
+    // masks are needed before the asset server runs, so read them the way it would
+    let base_path = env::var_os("BEVY_ASSET_ROOT")
+        .or_else(|| env::var_os("CARGO_MANIFEST_DIR"))
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(PathBuf::from))
+        })
+        .unwrap_or_default();
+    let full_path = base_path.join("assets").join(path);
+
+    let bytes = match std::fs::read(&full_path) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            warn!("Could not read maze mask {:?}: {}", full_path, error);
+            return None;
+        }
+    };
+
+    let extension = full_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("png");
+
+    let image = match Image::from_buffer(
+        &bytes,
+        ImageType::Extension(extension),
+        CompressedImageFormats::NONE,
+        false,
+        ImageSampler::Default,
+        RenderAssetUsages::default(),
+    ) {
+        Ok(image) => image,
+        Err(error) => {
+            warn!("Could not decode maze mask {:?}: {}", full_path, error);
+            return None;
+        }
+    };
+
+    let size = image.size();
+    let bitmap = (0..size.y)
+        .map(|y| {
+            (0..size.x)
+                .map(|x| {
+                    image.get_color_at(x, y).is_ok_and(|color| {
+                        let color = color.to_srgba();
+                        color.alpha > 0.5 && (color.red + color.green + color.blue) / 3. > 0.5
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    Some(bitmap)
+}
+
+fn text_bitmap(text: &str) -> Vec<Vec<bool>> {
+    let glyphs: Vec<&[&str; 5]> = text
+        .to_uppercase()
+        .chars()
+        .map(|character| {
+            GLYPHS
+                .iter()
+                .find(|(glyph_character, _)| *glyph_character == character)
+                .map(|(_, glyph)| glyph)
+                .unwrap_or(&BLANK_GLYPH)
+        })
+        .collect();
+
+    (0..5)
+        .map(|row| {
+            glyphs
+                .iter()
+                .enumerate()
+                .flat_map(|(index, glyph)| {
+                    // one column of spacing between characters
+                    let spacing = (index > 0).then_some(false);
+                    spacing
+                        .into_iter()
+                        .chain(glyph[row].chars().map(|pixel| pixel == '#'))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+const BLANK_GLYPH: [&str; 5] = ["...", "...", "...", "...", "..."];
+
+const GLYPHS: [(char, [&str; 5]); 36] = [
+    ('A', ["###", "#.#", "###", "#.#", "#.#"]),
+    ('B', ["##.", "#.#", "##.", "#.#", "##."]),
+    ('C', ["###", "#..", "#..", "#..", "###"]),
+    ('D', ["##.", "#.#", "#.#", "#.#", "##."]),
+    ('E', ["###", "#..", "##.", "#..", "###"]),
+    ('F', ["###", "#..", "##.", "#..", "#.."]),
+    ('G', ["###", "#..", "#.#", "#.#", "###"]),
+    ('H', ["#.#", "#.#", "###", "#.#", "#.#"]),
+    ('I', ["###", ".#.", ".#.", ".#.", "###"]),
+    ('J', ["..#", "..#", "..#", "#.#", "###"]),
+    ('K', ["#.#", "#.#", "##.", "#.#", "#.#"]),
+    ('L', ["#..", "#..", "#..", "#..", "###"]),
+    ('M', ["#.#", "###", "###", "#.#", "#.#"]),
+    ('N', ["##.", "#.#", "#.#", "#.#", "#.#"]),
+    ('O', ["###", "#.#", "#.#", "#.#", "###"]),
+    ('P', ["###", "#.#", "###", "#..", "#.."]),
+    ('Q', ["###", "#.#", "#.#", "###", "..#"]),
+    ('R', ["##.", "#.#", "##.", "#.#", "#.#"]),
+    ('S', ["###", "#..", "###", "..#", "###"]),
+    ('T', ["###", ".#.", ".#.", ".#.", ".#."]),
+    ('U', ["#.#", "#.#", "#.#", "#.#", "###"]),
+    ('V', ["#.#", "#.#", "#.#", "#.#", ".#."]),
+    ('W', ["#.#", "#.#", "###", "###", "#.#"]),
+    ('X', ["#.#", "#.#", ".#.", "#.#", "#.#"]),
+    ('Y', ["#.#", "#.#", ".#.", ".#.", ".#."]),
+    ('Z', ["###", "..#", ".#.", "#..", "###"]),
+    ('0', ["###", "#.#", "#.#", "#.#", "###"]),
+    ('1', [".#.", "##.", ".#.", ".#.", "###"]),
+    ('2', ["###", "..#", "###", "#..", "###"]),
+    ('3', ["###", "..#", "###", "..#", "###"]),
+    ('4', ["#.#", "#.#", "###", "..#", "..#"]),
+    ('5', ["###", "#..", "###", "..#", "###"]),
+    ('6', ["###", "#..", "###", "#.#", "###"]),
+    ('7', ["###", "..#", "..#", "..#", "..#"]),
+    ('8', ["###", "#.#", "###", "#.#", "###"]),
+    ('9', ["###", "#.#", "###", "..#", "###"]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_enables_every_cell() {
+        assert_eq!(MazeMask::Full.cells(4, 3), vec![true; 12]);
+    }
+
+    #[test]
+    fn circle_cuts_off_the_corners() {
+        let cells = MazeMask::Circle.cells(9, 9);
+
+        assert!(!cells[0]);
+        assert!(!cells[80]);
+        assert!(cells[4 * 9 + 4]);
+        assert!(cells[4 * 9]);
+    }
+
+    #[test]
+    fn ring_leaves_a_hole_in_the_centre() {
+        let cells = MazeMask::Ring { inner: 0.45 }.cells(9, 9);
+
+        assert!(!cells[4 * 9 + 4]);
+        assert!(cells[4 * 9]);
+        assert!(!cells[0]);
+    }
+
+    #[test]
+    fn text_enables_exactly_the_lit_pixels() {
+        let cells = MazeMask::Text("T".to_string()).cells(3, 5);
+        // the first row of the glyph is the top of the maze
+        let expected: Vec<bool> = ["###", ".#.", ".#.", ".#.", ".#."]
+            .iter()
+            .rev()
+            .flat_map(|row| row.chars().map(|pixel| pixel == '#'))
+            .collect();
+
+        assert_eq!(cells, expected);
+    }
+
+    #[test]
+    fn text_puts_a_column_between_characters() {
+        let cells = MazeMask::Text("II".to_string()).cells(7, 5);
+
+        assert!((0..5).all(|y| !cells[y * 7 + 3]));
+        assert!(cells[4 * 7 + 4]);
+    }
+
+    #[test]
+    fn masks_without_cells_fall_back_to_the_full_grid() {
+        assert_eq!(MazeMask::Text(" ".to_string()).cells(3, 5), vec![true; 15]);
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::{maze::Maze, maze_graph::Edge};
+
+/// What the origin-shift knows about the player when picking its next step
+pub struct MutationContext<'a> {
+    pub maze: &'a Maze,
+    pub player_position: Vec2,
+    /// How far the player sees, shorter than `Maze::view_distance` while their light is dimmed
+    pub view_distance: f32,
+    /// Edges of the player's floor in sight of the player
+    pub visible_edges: &'a HashSet<Edge>,
+    /// Seconds since the start of the game
+    pub elapsed: f32,
+}
+
+/// Decides where the root of the player's floor may move.
+///
+/// Every tick the policy first decides whether the maze shifts at all, then
+/// weighs each neighbour of the root; the step is drawn from those weights,
+/// a weight of zero forbidding the step.
+pub trait MazeMutationPolicy: Send + Sync {
+    fn should_shift(&mut self, _context: &MutationContext) -> bool {
+        true
+    }
+
+    fn weight(&self, context: &MutationContext, target: usize) -> f32;
+}
+
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MazeMutation {
+    Distance,
+    #[default]
+    LineOfSight,
+    RateLimited,
+    RegionFrozen,
+    TowardPlayer,
+    AwayFromPlayer,
+}
+
+/// Policy the origin-shift of the current level runs with, built from [`MazeMutation`]
+#[derive(Resource)]
+pub struct ActiveMutationPolicy(pub Box<dyn MazeMutationPolicy>);
+
+impl MazeMutation {
+    pub const ALL: [MazeMutation; 6] = [
+        MazeMutation::Distance,
+        MazeMutation::LineOfSight,
+        MazeMutation::RateLimited,
+        MazeMutation::RegionFrozen,
+        MazeMutation::TowardPlayer,
+        MazeMutation::AwayFromPlayer,
+    ];
+
+    pub fn policy(self) -> Box<dyn MazeMutationPolicy> {
+        match self {
+            MazeMutation::Distance => Box::new(Distance),
+            MazeMutation::LineOfSight => Box::new(LineOfSight),
+            MazeMutation::RateLimited => Box::new(RateLimited {
+                interval: 0.1,
+                last_shift: 0.,
+                inner: Box::new(Distance),
+            }),
+            MazeMutation::RegionFrozen => Box::new(RegionFrozen {
+                centre: Vec2::ZERO,
+                radius: 3.,
+                inner: Box::new(Distance),
+            }),
+            MazeMutation::TowardPlayer => Box::new(Biased {
+                bias: 2.,
+                inner: Box::new(Distance),
+            }),
+            MazeMutation::AwayFromPlayer => Box::new(Biased {
+                bias: -2.,
+                inner: Box::new(Distance),
+            }),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MazeMutation::Distance => "Distance",
+            MazeMutation::LineOfSight => "Sight",
+            MazeMutation::RateLimited => "Slow",
+            MazeMutation::RegionFrozen => "Frozen centre",
+            MazeMutation::TowardPlayer => "Hunting",
+            MazeMutation::AwayFromPlayer => "Fleeing",
+        }
+    }
+
+    pub fn next(self) -> MazeMutation {
+        let index = Self::ALL.iter().position(|m| *m == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// Only moves onto cells farther than the player sees
+pub struct Distance;
+
+impl MazeMutationPolicy for Distance {
+    fn weight(&self, context: &MutationContext, target: usize) -> f32 {
+        let distance = context
+            .player_position
+            .distance(context.maze.cell_position(target));
+        if distance > context.view_distance {
+            1.
+        } else {
+            0.
+        }
+    }
+}
+
+/// Only opens and closes passages the player can not see
+pub struct LineOfSight;
+
+impl MazeMutationPolicy for LineOfSight {
+    fn weight(&self, context: &MutationContext, target: usize) -> f32 {
+        let graph = context.maze.graph();
+
+        // the step opens the passage from the root and closes the one from the target
+        let opened = graph.edge(graph.root(), target);
+        let closed = graph
+            .parent(target)
+            .map(|parent| graph.edge(target, parent));
+
+        if context.visible_edges.contains(&opened)
+            || closed.is_some_and(|edge| context.visible_edges.contains(&edge))
+        {
+            0.
+        } else {
+            1.
+        }
+    }
+}
+
+/// Shifts at most once every `interval` seconds
+pub struct RateLimited {
+    pub interval: f32,
+    pub last_shift: f32,
+    pub inner: Box<dyn MazeMutationPolicy>,
+}
+
+impl MazeMutationPolicy for RateLimited {
+    fn should_shift(&mut self, context: &MutationContext) -> bool {
+        if context.elapsed - self.last_shift < self.interval {
+            return false;
+        }
+        self.last_shift = context.elapsed;
+        self.inner.should_shift(context)
+    }
+
+    fn weight(&self, context: &MutationContext, target: usize) -> f32 {
+        self.inner.weight(context, target)
+    }
+}
+
+/// Keeps the cells within `radius` cells of `centre` as they are
+pub struct RegionFrozen {
+    pub centre: Vec2,
+    pub radius: f32,
+    pub inner: Box<dyn MazeMutationPolicy>,
+}
+
+impl MazeMutationPolicy for RegionFrozen {
+    fn should_shift(&mut self, context: &MutationContext) -> bool {
+        self.inner.should_shift(context)
+    }
+
+    fn weight(&self, context: &MutationContext, target: usize) -> f32 {
+        let maze = context.maze;
+        let frozen = |cell: usize| {
+            maze.cell_position(cell).distance(self.centre) <= self.radius * maze.cell_size
+        };
+
+        let graph = maze.graph();
+        let root = graph.root();
+        // warmup and calls of the head may leave the root inside, it may only walk back out
+        let allowed = if frozen(root) {
+            maze.cell_position(target).distance(self.centre)
+                > maze.cell_position(root).distance(self.centre)
+        } else {
+            !frozen(target) && !graph.parent(target).is_some_and(frozen)
+        };
+
+        if allowed {
+            self.inner.weight(context, target)
+        } else {
+            0.
+        }
+    }
+}
+
+/// Favours steps toward the player for a positive `bias`, away from them for a negative one
+pub struct Biased {
+    pub bias: f32,
+    pub inner: Box<dyn MazeMutationPolicy>,
+}
+
+impl MazeMutationPolicy for Biased {
+    fn should_shift(&mut self, context: &MutationContext) -> bool {
+        self.inner.should_shift(context)
+    }
+
+    fn weight(&self, context: &MutationContext, target: usize) -> f32 {
+        let maze = context.maze;
+        let cells_away =
+            context.player_position.distance(maze.cell_position(target)) / maze.cell_size;
+
+        self.inner.weight(context, target) * cells_away.max(1.).powf(-self.bias)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{maze_graph::MazeGraph, maze_topology::Topology};
+
+    /// 7 by 7 square maze centred on the world origin, its tree rooted in `root`
+    fn maze(root: usize) -> Maze {
+        let mut graph = MazeGraph::new(7, 7, Topology::Square, vec![true; 49]);
+        let edges: Vec<(usize, usize)> = (0..49)
+            .filter_map(|cell| match (cell % 7, cell / 7) {
+                (0, 0) => None,
+                (0, _) => Some((cell - 7, cell)),
+                _ => Some((cell - 1, cell)),
+            })
+            .collect();
+        graph.set_tree(&edges, root);
+
+        Maze {
+            floors: vec![graph],
+            current_floor: 0,
+            stairs: Vec::new(),
+            spawn: 0,
+            exit: 0,
+            cell_size: 10.,
+            path_thickness: 8.,
+            view_distance: 30.,
+            origin: Vec2::new(30., 30.),
+        }
+    }
+
+    fn context<'a>(
+        maze: &'a Maze,
+        player_position: Vec2,
+        visible_edges: &'a HashSet<Edge>,
+        elapsed: f32,
+    ) -> MutationContext<'a> {
+        MutationContext {
+            maze,
+            player_position,
+            view_distance: maze.view_distance,
+            visible_edges,
+            elapsed,
+        }
+    }
+
+    #[test]
+    fn distance_keeps_cells_in_view_as_they_are() {
+        let maze = maze(0);
+        let visible_edges = HashSet::new();
+        let context = context(&maze, maze.cell_position(24), &visible_edges, 0.);
+
+        assert_eq!(Distance.weight(&context, 25), 0.);
+        assert_eq!(Distance.weight(&context, 27), 0.);
+        assert_eq!(Distance.weight(&context, 0), 1.);
+    }
+
+    #[test]
+    fn rate_limited_waits_for_the_interval() {
+        let maze = maze(0);
+        let visible_edges = HashSet::new();
+        let mut policy = RateLimited {
+            interval: 0.1,
+            last_shift: 0.,
+            inner: Box::new(Distance),
+        };
+        let mut shifts_at =
+            |elapsed| policy.should_shift(&context(&maze, Vec2::ZERO, &visible_edges, elapsed));
+
+        assert!(!shifts_at(0.05));
+        assert!(shifts_at(0.1));
+        assert!(!shifts_at(0.15));
+        assert!(shifts_at(0.25));
+    }
+
+    #[test]
+    fn region_frozen_leaves_the_cells_around_the_centre_alone() {
+        let maze = maze(0);
+        let visible_edges = HashSet::new();
+        let context = context(&maze, Vec2::splat(1000.), &visible_edges, 0.);
+        let policy = RegionFrozen {
+            centre: Vec2::ZERO,
+            radius: 1.,
+            inner: Box::new(Distance),
+        };
+
+        assert_eq!(policy.weight(&context, 1), 1.);
+        assert_eq!(policy.weight(&context, 24), 0.);
+        // the step would close the passage from the target to its parent in the region
+        assert_eq!(policy.weight(&context, 26), 0.);
+    }
+
+    #[test]
+    fn region_frozen_lets_the_root_walk_out_of_the_region() {
+        let maze = maze(24);
+        let visible_edges = HashSet::new();
+        let context = context(&maze, Vec2::splat(1000.), &visible_edges, 0.);
+        let policy = RegionFrozen {
+            centre: Vec2::ZERO,
+            radius: 3.,
+            inner: Box::new(Distance),
+        };
+        assert!(maze
+            .graph()
+            .neighbours(24)
+            .any(|(_, target)| policy.weight(&context, target) > 0.));
+
+        // halfway out, only steps away from the centre are left
+        let halfway = self::maze(25);
+        let context = self::context(&halfway, Vec2::splat(1000.), &visible_edges, 0.);
+        assert_eq!(policy.weight(&context, 24), 0.);
+        assert_eq!(policy.weight(&context, 26), 1.);
+    }
+
+    #[test]
+    fn biased_weighs_by_distance_to_the_player() {
+        let maze = maze(0);
+        let visible_edges = HashSet::new();
+        let context = context(&maze, maze.cell_position(0), &visible_edges, 0.);
+        let toward = Biased {
+            bias: 2.,
+            inner: Box::new(Distance),
+        };
+        let away = Biased {
+            bias: -2.,
+            inner: Box::new(Distance),
+        };
+
+        assert!(toward.weight(&context, 5) > toward.weight(&context, 6));
+        assert!(toward.weight(&context, 6) > toward.weight(&context, 48));
+        assert!(away.weight(&context, 5) < away.weight(&context, 6));
+        assert!(away.weight(&context, 6) < away.weight(&context, 48));
+    }
+}
@@ -5,7 +5,42 @@ pub struct MazeColor {
     pub path_color: Color,
     pub wall_color: Color,
     pub player_color: Color,
+    pub exit_color: Color,
+    pub light_color: Color,
+    pub station_color: Color,
+    pub head_color: Color,
 }
 
 #[derive(Resource)]
 pub struct MazeShape(pub Vec2);
+
+#[derive(Resource)]
+pub struct MazeSeed(pub u64);
+
+/// Amount of origin-shift steps taken before the first frame
+#[derive(Resource)]
+pub struct MazeWarmup(pub usize);
+
+/// Amount of maze layers stacked on top of each other, connected by stairs
+#[derive(Resource)]
+pub struct MazeFloors(pub usize);
+
+/// Share of dead ends opened up into loops after generating, 0 keeping a perfect maze
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct MazeBraid(pub f32);
+
+impl MazeBraid {
+    pub const PRESETS: [f32; 4] = [0., 0.25, 0.5, 1.];
+
+    pub fn next(self) -> MazeBraid {
+        let index = Self::PRESETS
+            .iter()
+            .position(|ratio| *ratio == self.0)
+            .unwrap_or(0);
+        MazeBraid(Self::PRESETS[(index + 1) % Self::PRESETS.len()])
+    }
+}
+
+/// Steps between the spawn and the exit, ignoring walls
+#[derive(Resource)]
+pub struct ExitDistance(pub usize);
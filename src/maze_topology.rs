@@ -0,0 +1,284 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::maze_graph::Direction;
+
+const SQUARE_DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+const HEX_DIRECTIONS: [Direction; 6] = [
+    Direction::Left,
+    Direction::Right,
+    Direction::UpLeft,
+    Direction::UpRight,
+    Direction::DownLeft,
+    Direction::DownRight,
+];
+
+const UP_TRIANGLE_DIRECTIONS: [Direction; 3] = [Direction::Left, Direction::Right, Direction::Down];
+
+const DOWN_TRIANGLE_DIRECTIONS: [Direction; 3] = [Direction::Left, Direction::Right, Direction::Up];
+
+/// Shape of the cells and how they tile the grid.
+///
+/// Cells are always addressed by a column `x` and row `y`, rows going up:
+/// - `Square`: regular grid
+/// - `Hex`: pointy-top hexagons, odd rows shifted half a cell to the right
+/// - `Triangle`: alternating triangles, pointing up when `x + y` is even
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Topology {
+    #[default]
+    Square,
+    Hex,
+    Triangle,
+}
+
+impl Topology {
+    pub const ALL: [Topology; 3] = [Topology::Square, Topology::Hex, Topology::Triangle];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Topology::Square => "Square",
+            Topology::Hex => "Hex",
+            Topology::Triangle => "Triangle",
+        }
+    }
+
+    pub fn next(self) -> Topology {
+        let index = Self::ALL.iter().position(|t| *t == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// Directions in which the cell at `coords` has a neighbouring side
+    pub fn directions(self, coords: IVec2) -> &'static [Direction] {
+        match self {
+            Topology::Square => &SQUARE_DIRECTIONS,
+            Topology::Hex => &HEX_DIRECTIONS,
+            Topology::Triangle if points_up(coords) => &UP_TRIANGLE_DIRECTIONS,
+            Topology::Triangle => &DOWN_TRIANGLE_DIRECTIONS,
+        }
+    }
+
+    /// Coordinates of the neighbour in `direction`, which may lie outside the grid
+    pub fn neighbour(self, coords: IVec2, direction: Direction) -> Option<IVec2> {
+        if !self.directions(coords).contains(&direction) {
+            return None;
+        }
+
+        let odd_row = coords.y.rem_euclid(2) == 1;
+        let offset = match direction {
+            Direction::Up => IVec2::new(0, 1),
+            Direction::Down => IVec2::new(0, -1),
+            Direction::Left => IVec2::new(-1, 0),
+            Direction::Right => IVec2::new(1, 0),
+            Direction::UpLeft => IVec2::new(if odd_row { 0 } else { -1 }, 1),
+            Direction::UpRight => IVec2::new(if odd_row { 1 } else { 0 }, 1),
+            Direction::DownLeft => IVec2::new(if odd_row { 0 } else { -1 }, -1),
+            Direction::DownRight => IVec2::new(if odd_row { 1 } else { 0 }, -1),
+        };
+
+        Some(coords + offset)
+    }
+
+    /// Centre of the cell at `coords`, before the maze gets centred on the origin
+    pub fn centre(self, coords: IVec2, cell_size: f32) -> Vec2 {
+        let coords_f = coords.as_vec2();
+
+        match self {
+            Topology::Square => coords_f * cell_size,
+            Topology::Hex => {
+                let shift = if coords.y.rem_euclid(2) == 1 { 0.5 } else { 0. };
+                Vec2::new(
+                    (coords_f.x + shift) * cell_size,
+                    coords_f.y * cell_size * HEX_ROW_FACTOR,
+                )
+            }
+            Topology::Triangle => {
+                let (side, height) = triangle_size(cell_size);
+                let centroid = if points_up(coords) { 1. / 3. } else { 2. / 3. };
+                Vec2::new(
+                    (coords_f.x + 1.) * side * 0.5,
+                    (coords_f.y + centroid) * height,
+                )
+            }
+        }
+    }
+
+    /// Cell closest to `position` (in the same space as [`Topology::centre`]), ignoring grid bounds
+    pub fn closest(self, position: Vec2, cell_size: f32) -> IVec2 {
+        let estimate = match self {
+            Topology::Square => (position / cell_size).round().as_ivec2(),
+            Topology::Hex => {
+                let y = (position.y / (cell_size * HEX_ROW_FACTOR)).round() as i32;
+                let shift = if y.rem_euclid(2) == 1 { 0.5 } else { 0. };
+                IVec2::new((position.x / cell_size - shift).round() as i32, y)
+            }
+            Topology::Triangle => {
+                let (side, height) = triangle_size(cell_size);
+                IVec2::new(
+                    (position.x / (side * 0.5) - 1.).round() as i32,
+                    (position.y / height).floor() as i32,
+                )
+            }
+        };
+
+        // the estimate is off by at most one row and a couple of columns
+        let mut closest = estimate;
+        for y in -1..=1 {
+            for x in -2..=2 {
+                let candidate = estimate + IVec2::new(x, y);
+                if self.centre(candidate, cell_size).distance_squared(position)
+                    < self.centre(closest, cell_size).distance_squared(position)
+                {
+                    closest = candidate;
+                }
+            }
+        }
+        closest
+    }
+
+    /// Corners of the cell at `coords`, relative to its centre
+    pub fn corners(self, coords: IVec2, cell_size: f32) -> Vec<Vec2> {
+        match self {
+            Topology::Square => vec![
+                Vec2::new(-0.5, -0.5) * cell_size,
+                Vec2::new(0.5, -0.5) * cell_size,
+                Vec2::new(0.5, 0.5) * cell_size,
+                Vec2::new(-0.5, 0.5) * cell_size,
+            ],
+            Topology::Hex => (0..6)
+                .map(|corner| {
+                    let angle = (30. + 60. * corner as f32).to_radians();
+                    Vec2::from_angle(angle) * self.side_length(cell_size)
+                })
+                .collect(),
+            Topology::Triangle => {
+                let (side, height) = triangle_size(cell_size);
+                let flip = if points_up(coords) { 1. } else { -1. };
+                vec![
+                    Vec2::new(-side * 0.5, -height / 3. * flip),
+                    Vec2::new(side * 0.5, -height / 3. * flip),
+                    Vec2::new(0., height * 2. / 3. * flip),
+                ]
+            }
+        }
+    }
+
+    /// Length of the side shared by two neighbouring cells
+    pub fn side_length(self, cell_size: f32) -> f32 {
+        match self {
+            Topology::Square => cell_size,
+            Topology::Hex => cell_size / 3f32.sqrt(),
+            Topology::Triangle => triangle_size(cell_size).0,
+        }
+    }
+}
+
+/// Vertical distance between hexagon rows relative to their width
+const HEX_ROW_FACTOR: f32 = 0.866_025_4;
+
+fn points_up(coords: IVec2) -> bool {
+    (coords.x + coords.y).rem_euclid(2) == 0
+}
+
+/// Side and height of a triangle covering about the same area as a square cell
+fn triangle_size(cell_size: f32) -> (f32, f32) {
+    let side = cell_size * 1.5;
+    (side, side * HEX_ROW_FACTOR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opposite(direction: Direction) -> Direction {
+        match direction {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+            Direction::UpLeft => Direction::DownRight,
+            Direction::UpRight => Direction::DownLeft,
+            Direction::DownLeft => Direction::UpRight,
+            Direction::DownRight => Direction::UpLeft,
+        }
+    }
+
+    fn grid() -> impl Iterator<Item = IVec2> {
+        (0..6).flat_map(|y| (0..6).map(move |x| IVec2::new(x, y)))
+    }
+
+    #[test]
+    fn hex_rows_shift_their_diagonal_neighbours() {
+        let even = IVec2::new(2, 0);
+        assert_eq!(
+            Topology::Hex.neighbour(even, Direction::UpLeft),
+            Some(IVec2::new(1, 1))
+        );
+        assert_eq!(
+            Topology::Hex.neighbour(even, Direction::UpRight),
+            Some(IVec2::new(2, 1))
+        );
+
+        let odd = IVec2::new(2, 1);
+        assert_eq!(
+            Topology::Hex.neighbour(odd, Direction::UpLeft),
+            Some(IVec2::new(2, 2))
+        );
+        assert_eq!(
+            Topology::Hex.neighbour(odd, Direction::DownRight),
+            Some(IVec2::new(3, 0))
+        );
+        assert_eq!(Topology::Hex.neighbour(odd, Direction::Up), None);
+    }
+
+    #[test]
+    fn triangles_alternate_between_pointing_up_and_down() {
+        let up = IVec2::new(0, 0);
+        let down = IVec2::new(1, 0);
+
+        assert_eq!(Topology::Triangle.directions(up), &UP_TRIANGLE_DIRECTIONS);
+        assert_eq!(
+            Topology::Triangle.directions(down),
+            &DOWN_TRIANGLE_DIRECTIONS
+        );
+        assert_eq!(
+            Topology::Triangle.neighbour(down, Direction::Up),
+            Some(IVec2::new(1, 1))
+        );
+        assert_eq!(Topology::Triangle.neighbour(up, Direction::Up), None);
+    }
+
+    #[test]
+    fn neighbours_are_mutual() {
+        for topology in Topology::ALL {
+            for coords in grid() {
+                for &direction in topology.directions(coords) {
+                    let neighbour = topology.neighbour(coords, direction).unwrap();
+                    assert_eq!(
+                        topology.neighbour(neighbour, opposite(direction)),
+                        Some(coords),
+                        "{} neighbour of {} in {:?} does not point back",
+                        topology.name(),
+                        coords,
+                        direction
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn closest_finds_the_cell_of_its_centre() {
+        for topology in Topology::ALL {
+            for coords in grid() {
+                let centre = topology.centre(coords, 10.);
+                assert_eq!(topology.closest(centre, 10.), coords);
+            }
+        }
+    }
+}
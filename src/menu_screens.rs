@@ -1,6 +1,16 @@
 use bevy::prelude::*;
 
-use crate::gamestate::GameState;
+use crate::{
+    gamestate::GameState,
+    hud::Score,
+    maze::Maze,
+    maze_generators::MazeAlgorithm,
+    maze_mask::MazeMask,
+    maze_policies::MazeMutation,
+    maze_specs::{MazeBraid, MazeSeed},
+    maze_topology::Topology,
+    objectives::LightCoverage,
+};
 
 pub struct MenuPlugin;
 
@@ -27,8 +37,26 @@ impl Plugin for MenuPlugin {
             OnEnter(MenuState::Settings(SettingsType::Controls)),
             settings_screen.run_if(in_state(GameState::MainMenu)),
         );
-        app.add_systems(Update, button_system.run_if(in_state(GameState::MainMenu)));
+        app.add_systems(OnEnter(GameState::Victory), results_screen);
+        app.add_systems(OnEnter(GameState::GameOver), results_screen);
+        app.add_systems(
+            Update,
+            button_system.run_if(
+                in_state(GameState::MainMenu)
+                    .or(in_state(GameState::Victory))
+                    .or(in_state(GameState::GameOver)),
+            ),
+        );
+        app.add_systems(
+            Update,
+            (setting_button_system, edit_seed, update_setting_texts)
+                .chain()
+                .run_if(in_state(MenuState::Settings(SettingsType::General)))
+                .run_if(in_state(GameState::MainMenu)),
+        );
         app.add_systems(OnExit(GameState::MainMenu), despawn_menu);
+        app.add_systems(OnExit(GameState::Victory), despawn_menu);
+        app.add_systems(OnExit(GameState::GameOver), despawn_menu);
         app.add_systems(OnExit(MenuState::Main), despawn_menu);
         app.add_systems(
             OnExit(MenuState::Settings(SettingsType::General)),
@@ -47,9 +75,9 @@ impl Plugin for MenuPlugin {
     }
 }
 
-const NORMAL_BUTTON_COLOR: Color = Color::srgba(0.1, 0.4, 0.4, 0.3);
-const PRESSED_BUTTON_COLOR: Color = Color::srgba(0.1, 0.4, 0.1, 0.5);
-const HOVERED_BUTTON_COLOR: Color = Color::srgba(0.1, 0.4, 0.1, 0.3);
+pub const NORMAL_BUTTON_COLOR: Color = Color::srgba(0.1, 0.4, 0.4, 0.3);
+pub const PRESSED_BUTTON_COLOR: Color = Color::srgba(0.1, 0.4, 0.1, 0.5);
+pub const HOVERED_BUTTON_COLOR: Color = Color::srgba(0.1, 0.4, 0.1, 0.3);
 
 #[derive(Component)]
 struct MainScreenUI;
@@ -57,6 +85,42 @@ struct MainScreenUI;
 struct SettingsScreenUI;
 #[derive(Component)]
 struct CreditScreenUI;
+#[derive(Component)]
+struct ResultsScreenUI;
+
+/// Text showing the current value of a maze setting
+#[derive(Component, Debug)]
+enum SettingText {
+    Seed,
+    Algorithm,
+    Mask,
+    Topology,
+    Braid,
+    Mutation,
+}
+
+#[derive(Component, Debug)]
+enum SettingButton {
+    RandomSeed,
+    NextAlgorithm,
+    NextMask,
+    NextTopology,
+    NextBraid,
+    NextMutation,
+}
+
+const SEED_DIGIT_KEYS: [KeyCode; 10] = [
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
 
 #[derive(States, Debug, Default, Clone, Eq, PartialEq, Hash)]
 enum MenuState {
@@ -115,6 +179,89 @@ fn button_system(
     }
 }
 
+#[allow(clippy::type_complexity)]
+fn setting_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &SettingButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut seed: ResMut<MazeSeed>,
+    mut algorithm: ResMut<MazeAlgorithm>,
+    mut mask: ResMut<MazeMask>,
+    mut topology: ResMut<Topology>,
+    mut braid: ResMut<MazeBraid>,
+    mut mutation: ResMut<MazeMutation>,
+) {
+    for (interaction, mut bg_color, setting) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *bg_color = BackgroundColor(PRESSED_BUTTON_COLOR);
+                match setting {
+                    SettingButton::RandomSeed => seed.0 = rand::random(),
+                    SettingButton::NextAlgorithm => *algorithm = algorithm.next(),
+                    SettingButton::NextMask => *mask = mask.next(),
+                    SettingButton::NextTopology => *topology = topology.next(),
+                    SettingButton::NextBraid => *braid = braid.next(),
+                    SettingButton::NextMutation => *mutation = mutation.next(),
+                }
+            }
+            Interaction::Hovered => {
+                *bg_color = BackgroundColor(HOVERED_BUTTON_COLOR);
+            }
+            Interaction::None => {
+                *bg_color = BackgroundColor(NORMAL_BUTTON_COLOR);
+            }
+        }
+    }
+}
+
+// Type digits to enter a seed, backspace to remove the last one
+fn edit_seed(keys: Res<ButtonInput<KeyCode>>, mut seed: ResMut<MazeSeed>) {
+    for (digit, key) in SEED_DIGIT_KEYS.iter().enumerate() {
+        if keys.just_pressed(*key) {
+            if let Some(new_seed) = seed
+                .0
+                .checked_mul(10)
+                .and_then(|s| s.checked_add(digit as u64))
+            {
+                seed.0 = new_seed;
+            }
+        }
+    }
+
+    if keys.just_pressed(KeyCode::Backspace) {
+        seed.0 /= 10;
+    }
+}
+
+fn update_setting_texts(
+    seed: Res<MazeSeed>,
+    algorithm: Res<MazeAlgorithm>,
+    mask: Res<MazeMask>,
+    topology: Res<Topology>,
+    braid: Res<MazeBraid>,
+    mutation: Res<MazeMutation>,
+    mut texts: Query<(&mut Text, &SettingText)>,
+) {
+    for (mut text, setting) in &mut texts {
+        match setting {
+            SettingText::Seed if seed.is_changed() => text.0 = seed.0.to_string(),
+            SettingText::Algorithm if algorithm.is_changed() => {
+                text.0 = algorithm.name().to_string()
+            }
+            SettingText::Mask if mask.is_changed() => text.0 = mask.name().to_string(),
+            SettingText::Topology if topology.is_changed() => text.0 = topology.name().to_string(),
+            SettingText::Braid if braid.is_changed() => text.0 = braid_name(*braid),
+            SettingText::Mutation if mutation.is_changed() => text.0 = mutation.name().to_string(),
+            _ => {}
+        }
+    }
+}
+
+fn braid_name(braid: MazeBraid) -> String {
+    format!("{:.0}%", braid.0 * 100.)
+}
+
 #[allow(clippy::type_complexity)]
 fn despawn_menu(
     mut commands: Commands,
@@ -124,6 +271,7 @@ fn despawn_menu(
             With<MainScreenUI>,
             With<SettingsScreenUI>,
             With<CreditScreenUI>,
+            With<ResultsScreenUI>,
         )>,
     >,
 ) {
@@ -380,10 +528,133 @@ fn credit_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
         });
 }
 
+// Shown when the run ended, won or lost
+#[allow(clippy::too_many_arguments)]
+fn results_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game_state: Res<State<GameState>>,
+    score: Res<Score>,
+    seed: Res<MazeSeed>,
+    maze: Res<Maze>,
+    coverage: Res<LightCoverage>,
+) {
+    let font = asset_server.load("fonts/MatrixtypeDisplay-9MyE5.ttf");
+    let title = match game_state.get() {
+        GameState::Victory => "Escaped",
+        _ => "Game Over",
+    };
+    let results = [
+        format!("Time: {}s", score.0),
+        format!("Seed: {}", seed.0),
+        format!("Floors: {}", maze.floors.len()),
+        format!("Lit: {:.0}%", coverage.0),
+    ];
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(70.),
+                height: Val::Percent(70.0),
+                align_items: AlignItems::Center,
+                align_self: AlignSelf::Center,
+                justify_content: JustifyContent::SpaceBetween,
+                justify_self: JustifySelf::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.05)),
+            BorderRadius::all(Val::Px(10.0)),
+            BorderColor(Color::srgb(0.0, 0.0, 0.0)),
+            ResultsScreenUI,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(title),
+                TextFont {
+                    font: font.clone(),
+                    font_size: 50.0,
+                    ..default()
+                },
+                TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+            ));
+            parent
+                .spawn(Node {
+                    width: Val::Auto,
+                    height: Val::Auto,
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(5.)),
+                    justify_content: JustifyContent::SpaceBetween,
+                    row_gap: Val::Px(5.0),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for result in results {
+                        parent.spawn((
+                            Text::new(result),
+                            TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                            TextFont {
+                                font: font.clone(),
+                                font_size: 15.0,
+                                ..default()
+                            },
+                        ));
+                    }
+                });
+            parent
+                .spawn(Node {
+                    width: Val::Auto,
+                    height: Val::Auto,
+                    column_gap: Val::Px(5.0),
+                    margin: UiRect::all(Val::Px(5.)),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (label, destination) in [
+                        ("Play Again", NextStateDestination::Game(GameState::InGame)),
+                        ("Quit", NextStateDestination::Menu(MenuState::Quit)),
+                    ] {
+                        parent
+                            .spawn((
+                                Node {
+                                    width: Val::Px(100.),
+                                    height: Val::Px(30.),
+                                    align_items: AlignItems::Center,
+                                    justify_content: JustifyContent::Center,
+                                    ..default()
+                                },
+                                Button,
+                                BackgroundColor(NORMAL_BUTTON_COLOR),
+                                BorderRadius::MAX,
+                                destination,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    Text::new(label),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: 15.0,
+                                        ..default()
+                                    },
+                                    TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                ));
+                            });
+                    }
+                });
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
 fn settings_screen(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     menu_settings: Res<State<MenuState>>,
+    seed: Res<MazeSeed>,
+    algorithm: Res<MazeAlgorithm>,
+    mask: Res<MazeMask>,
+    topology: Res<Topology>,
+    braid: Res<MazeBraid>,
+    mutation: Res<MazeMutation>,
 ) {
     let font = asset_server.load("fonts/MatrixtypeDisplay-9MyE5.ttf");
 
@@ -517,6 +788,291 @@ fn settings_screen(
                             },
                             TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
                         ));
+                        parent
+                            .spawn(Node {
+                                width: Val::Auto,
+                                height: Val::Auto,
+                                align_items: AlignItems::Center,
+                                column_gap: Val::Px(5.0),
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    Text::new("Seed:"),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: 15.0,
+                                        ..default()
+                                    },
+                                    TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                ));
+                                parent.spawn((
+                                    Text::new(seed.0.to_string()),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: 15.0,
+                                        ..default()
+                                    },
+                                    TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                    SettingText::Seed,
+                                ));
+                                parent
+                                    .spawn((
+                                        Button,
+                                        Node {
+                                            width: Val::Auto,
+                                            height: Val::Px(30.),
+                                            align_items: AlignItems::Center,
+                                            justify_content: JustifyContent::Center,
+                                            padding: UiRect::all(Val::Px(5.)),
+                                            ..default()
+                                        },
+                                        BackgroundColor(NORMAL_BUTTON_COLOR),
+                                        BorderRadius::MAX,
+                                        SettingButton::RandomSeed,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn((
+                                            Text::new("Random"),
+                                            TextFont {
+                                                font: font.clone(),
+                                                font_size: 15.0,
+                                                ..default()
+                                            },
+                                            TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                        ));
+                                    });
+                            });
+                        parent
+                            .spawn(Node {
+                                width: Val::Auto,
+                                height: Val::Auto,
+                                align_items: AlignItems::Center,
+                                column_gap: Val::Px(5.0),
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    Text::new("Algorithm:"),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: 15.0,
+                                        ..default()
+                                    },
+                                    TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                ));
+                                parent
+                                    .spawn((
+                                        Button,
+                                        Node {
+                                            width: Val::Auto,
+                                            height: Val::Px(30.),
+                                            align_items: AlignItems::Center,
+                                            justify_content: JustifyContent::Center,
+                                            padding: UiRect::all(Val::Px(5.)),
+                                            ..default()
+                                        },
+                                        BackgroundColor(NORMAL_BUTTON_COLOR),
+                                        BorderRadius::MAX,
+                                        SettingButton::NextAlgorithm,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn((
+                                            Text::new(algorithm.name()),
+                                            TextFont {
+                                                font: font.clone(),
+                                                font_size: 15.0,
+                                                ..default()
+                                            },
+                                            TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                            SettingText::Algorithm,
+                                        ));
+                                    });
+                            });
+                        parent
+                            .spawn(Node {
+                                width: Val::Auto,
+                                height: Val::Auto,
+                                align_items: AlignItems::Center,
+                                column_gap: Val::Px(5.0),
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    Text::new("Shape:"),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: 15.0,
+                                        ..default()
+                                    },
+                                    TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                ));
+                                parent
+                                    .spawn((
+                                        Button,
+                                        Node {
+                                            width: Val::Auto,
+                                            height: Val::Px(30.),
+                                            align_items: AlignItems::Center,
+                                            justify_content: JustifyContent::Center,
+                                            padding: UiRect::all(Val::Px(5.)),
+                                            ..default()
+                                        },
+                                        BackgroundColor(NORMAL_BUTTON_COLOR),
+                                        BorderRadius::MAX,
+                                        SettingButton::NextMask,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn((
+                                            Text::new(mask.name()),
+                                            TextFont {
+                                                font: font.clone(),
+                                                font_size: 15.0,
+                                                ..default()
+                                            },
+                                            TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                            SettingText::Mask,
+                                        ));
+                                    });
+                            });
+                        parent
+                            .spawn(Node {
+                                width: Val::Auto,
+                                height: Val::Auto,
+                                align_items: AlignItems::Center,
+                                column_gap: Val::Px(5.0),
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    Text::new("Grid:"),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: 15.0,
+                                        ..default()
+                                    },
+                                    TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                ));
+                                parent
+                                    .spawn((
+                                        Button,
+                                        Node {
+                                            width: Val::Auto,
+                                            height: Val::Px(30.),
+                                            align_items: AlignItems::Center,
+                                            justify_content: JustifyContent::Center,
+                                            padding: UiRect::all(Val::Px(5.)),
+                                            ..default()
+                                        },
+                                        BackgroundColor(NORMAL_BUTTON_COLOR),
+                                        BorderRadius::MAX,
+                                        SettingButton::NextTopology,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn((
+                                            Text::new(topology.name()),
+                                            TextFont {
+                                                font: font.clone(),
+                                                font_size: 15.0,
+                                                ..default()
+                                            },
+                                            TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                            SettingText::Topology,
+                                        ));
+                                    });
+                            });
+                        parent
+                            .spawn(Node {
+                                width: Val::Auto,
+                                height: Val::Auto,
+                                align_items: AlignItems::Center,
+                                column_gap: Val::Px(5.0),
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    Text::new("Braid:"),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: 15.0,
+                                        ..default()
+                                    },
+                                    TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                ));
+                                parent
+                                    .spawn((
+                                        Button,
+                                        Node {
+                                            width: Val::Auto,
+                                            height: Val::Px(30.),
+                                            align_items: AlignItems::Center,
+                                            justify_content: JustifyContent::Center,
+                                            padding: UiRect::all(Val::Px(5.)),
+                                            ..default()
+                                        },
+                                        BackgroundColor(NORMAL_BUTTON_COLOR),
+                                        BorderRadius::MAX,
+                                        SettingButton::NextBraid,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn((
+                                            Text::new(braid_name(*braid)),
+                                            TextFont {
+                                                font: font.clone(),
+                                                font_size: 15.0,
+                                                ..default()
+                                            },
+                                            TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                            SettingText::Braid,
+                                        ));
+                                    });
+                            });
+                        parent
+                            .spawn(Node {
+                                width: Val::Auto,
+                                height: Val::Auto,
+                                align_items: AlignItems::Center,
+                                column_gap: Val::Px(5.0),
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    Text::new("Shifting:"),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: 15.0,
+                                        ..default()
+                                    },
+                                    TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                ));
+                                parent
+                                    .spawn((
+                                        Button,
+                                        Node {
+                                            width: Val::Auto,
+                                            height: Val::Px(30.),
+                                            align_items: AlignItems::Center,
+                                            justify_content: JustifyContent::Center,
+                                            padding: UiRect::all(Val::Px(5.)),
+                                            ..default()
+                                        },
+                                        BackgroundColor(NORMAL_BUTTON_COLOR),
+                                        BorderRadius::MAX,
+                                        SettingButton::NextMutation,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn((
+                                            Text::new(mutation.name()),
+                                            TextFont {
+                                                font: font.clone(),
+                                                font_size: 15.0,
+                                                ..default()
+                                            },
+                                            TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                                            SettingText::Mutation,
+                                        ));
+                                    });
+                            });
                     }
                     MenuState::Settings(SettingsType::Audio) => {
                         parent.spawn((
@@ -0,0 +1,249 @@
+use std::iter::once;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    maze::{Maze, MazeEdgeChanged, MazeRebuilt},
+    maze_graph::{Direction, EdgeChange},
+    maze_specs::MazeSeed,
+};
+
+/// Navigation shared by hunters and guards
+pub struct NpcPlugin;
+
+impl Plugin for NpcPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(NpcRng(StdRng::seed_from_u64(0)));
+        app.add_systems(Update, reseed_npc_rng);
+    }
+}
+
+/// Random source for placing NPCs, kept apart from [`MazeRng`](crate::maze::MazeRng) so
+/// spawning them does not change how the maze evolves
+#[derive(Resource)]
+pub struct NpcRng(pub StdRng);
+
+// Every maze places its NPCs the same way for the same seed
+pub fn reseed_npc_rng(
+    mut rebuilt: EventReader<MazeRebuilt>,
+    seed: Res<MazeSeed>,
+    mut rng: ResMut<NpcRng>,
+) {
+    if rebuilt.is_empty() {
+        return;
+    }
+    rebuilt.clear();
+    // a different stream than the maze draws from the same seed
+    rng.0 = StdRng::seed_from_u64(!seed.0);
+}
+
+/// Pairs of cells on the current floor the origin-shift put a wall between
+pub fn closed_passages<'a>(
+    changes: impl Iterator<Item = &'a MazeEdgeChanged>,
+    maze: &Maze,
+) -> Vec<(usize, usize)> {
+    let graph = maze.graph();
+    changes
+        .filter(|event| event.floor == maze.current_floor)
+        .filter_map(|event| match event.change {
+            EdgeChange::Closed(edge) => {
+                Some((edge.cell, graph.neighbour(edge.cell, edge.direction)?))
+            }
+            EdgeChange::Opened(_) => None,
+        })
+        .collect()
+}
+
+/// Whether a closed passage lies on a route, including the leg being walked
+pub fn route_blocked(route: &[usize], closed: &[(usize, usize)]) -> bool {
+    route.windows(2).any(|step| {
+        closed
+            .iter()
+            .any(|&(a, b)| (step[0], step[1]) == (a, b) || (step[0], step[1]) == (b, a))
+    })
+}
+
+/// Points a route at `target`, starting over from the cell last reached.
+///
+/// A passage closing on the leg being walked puts the NPC back in the cell it
+/// came from, so it never walks through the new wall.
+pub fn replan_route(
+    route: &mut Vec<usize>,
+    transform: &mut Transform,
+    target: usize,
+    closed: &[(usize, usize)],
+    maze: &Maze,
+) {
+    let position = transform.translation.truncate();
+    let Some(from) = route.first().copied().or_else(|| maze.cell_at(position)) else {
+        return;
+    };
+    let blocked = route_blocked(route, closed);
+    if !blocked && route.last() == Some(&target) {
+        return;
+    }
+
+    let path = maze.graph().tree_path(from, target).unwrap_or_default();
+    if blocked {
+        transform.translation = maze.cell_position(from).extend(transform.translation.z);
+        *route = path;
+    } else if position != maze.cell_position(from) {
+        // head back to the cell left before turning, cutting across would clip walls
+        *route = once(from).chain(path).collect();
+    } else {
+        *route = path;
+    }
+}
+
+/// Moves along the first leg of a route, which starts with the cell last reached,
+/// dropping that cell once the next one is reached. Returns the direction moved in,
+/// `None` once no leg is left.
+pub fn walk_route(
+    route: &mut Vec<usize>,
+    transform: &mut Transform,
+    distance: f32,
+    maze: &Maze,
+) -> Option<Vec2> {
+    let &next = route.get(1)?;
+    let offset = maze.cell_position(next) - transform.translation.truncate();
+
+    if offset.length() <= distance {
+        transform.translation = maze.cell_position(next).extend(transform.translation.z);
+        route.remove(0);
+    } else {
+        transform.translation += (offset.normalize() * distance).extend(0.);
+    }
+    Some(offset)
+}
+
+/// Sprite direction for a heading, preferring left and right over up and down like the player
+pub fn facing(heading: Vec2) -> Direction {
+    if heading.x.abs() > heading.y.abs() * 0.5 {
+        if heading.x > 0. {
+            Direction::Right
+        } else {
+            Direction::Left
+        }
+    } else if heading.y > 0. {
+        Direction::Up
+    } else {
+        Direction::Down
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        maze_graph::{Edge, MazeGraph},
+        maze_topology::Topology,
+    };
+
+    /// Two floors of 3 by 1 cells, all passages open
+    fn corridors() -> Maze {
+        let corridor = || {
+            let mut graph = MazeGraph::new(3, 1, Topology::Square, vec![true; 3]);
+            graph.set_tree(&[(0, 1), (1, 2)], 0);
+            graph
+        };
+
+        Maze {
+            floors: vec![corridor(), corridor()],
+            current_floor: 0,
+            stairs: Vec::new(),
+            spawn: 0,
+            exit: 0,
+            cell_size: 10.,
+            path_thickness: 8.,
+            view_distance: 30.,
+            origin: Vec2::ZERO,
+        }
+    }
+
+    fn closing(floor: usize, cell: usize, direction: Direction) -> MazeEdgeChanged {
+        MazeEdgeChanged {
+            floor,
+            change: EdgeChange::Closed(Edge { cell, direction }),
+        }
+    }
+
+    #[test]
+    fn closed_passages_only_lists_closings_on_the_current_floor() {
+        let maze = corridors();
+        let changes = [
+            closing(0, 1, Direction::Right),
+            closing(1, 0, Direction::Right),
+            MazeEdgeChanged {
+                floor: 0,
+                change: EdgeChange::Opened(Edge {
+                    cell: 0,
+                    direction: Direction::Right,
+                }),
+            },
+        ];
+
+        assert_eq!(closed_passages(changes.iter(), &maze), vec![(1, 2)]);
+    }
+
+    #[test]
+    fn route_blocked_checks_the_leg_being_walked() {
+        let route = [3, 4, 5, 8];
+
+        assert!(route_blocked(&route, &[(3, 4)]));
+        assert!(route_blocked(&route, &[(8, 5)]));
+        assert!(!route_blocked(&route, &[(0, 3)]));
+        assert!(!route_blocked(&[3], &[(3, 4)]));
+    }
+
+    #[test]
+    fn replan_route_steps_back_from_a_closed_passage() {
+        let maze = corridors();
+        let mut route = vec![0, 1, 2];
+        let mut transform = Transform::from_translation(Vec3::new(7., 0., 1.));
+
+        replan_route(&mut route, &mut transform, 2, &[(0, 1)], &maze);
+
+        assert_eq!(transform.translation, Vec3::new(0., 0., 1.));
+        assert_eq!(route[0], 0);
+    }
+
+    #[test]
+    fn replan_route_returns_to_the_cell_left_before_turning() {
+        let maze = corridors();
+        let mut route = vec![1, 2];
+        let mut transform = Transform::from_translation(Vec3::new(13., 0., 0.));
+
+        replan_route(&mut route, &mut transform, 0, &[], &maze);
+
+        assert_eq!(route, vec![1, 1, 0]);
+    }
+
+    #[test]
+    fn walk_route_drops_the_cell_left_once_the_next_is_reached() {
+        let maze = corridors();
+        let mut route = vec![0, 1, 2];
+        let mut transform = Transform::default();
+
+        assert_eq!(
+            walk_route(&mut route, &mut transform, 4., &maze),
+            Some(Vec2::new(10., 0.))
+        );
+        assert_eq!(route, vec![0, 1, 2]);
+        assert_eq!(transform.translation, Vec3::new(4., 0., 0.));
+
+        walk_route(&mut route, &mut transform, 10., &maze);
+        assert_eq!(route, vec![1, 2]);
+        assert_eq!(transform.translation, Vec3::new(10., 0., 0.));
+    }
+
+    #[test]
+    fn walk_route_stands_still_without_a_leg() {
+        let maze = corridors();
+        let mut transform = Transform::default();
+
+        assert_eq!(walk_route(&mut Vec::new(), &mut transform, 4., &maze), None);
+        assert_eq!(walk_route(&mut vec![0], &mut transform, 4., &maze), None);
+        assert_eq!(transform.translation, Vec3::ZERO);
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_light_2d::prelude::*;
+
+use crate::{
+    gamestate::GameState,
+    maze::{Maze, MazeEdgeChanged, MazeFloorChanged, MazeNode, MazeRebuilt},
+    maze_graph::EdgeChange,
+    maze_specs::{MazeColor, MazeSeed},
+    player::{player_alive, Player},
+};
+
+pub struct ObjectivePlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for ObjectivePlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LightCoverage>();
+        app.add_systems(Update, spawn_cell_lights);
+        app.add_systems(
+            Update,
+            (
+                // a dying player can no longer win
+                reach_exit.run_if(player_alive),
+                light_visited_cells,
+                darken_disconnected_cells,
+                update_coverage.run_if(player_alive),
+            )
+                .chain()
+                .after(spawn_cell_lights)
+                .run_if(in_state(self.state.clone())),
+        );
+        app.add_systems(OnExit(GameState::Victory), start_new_run);
+        app.add_systems(OnExit(GameState::GameOver), start_new_run);
+    }
+}
+
+/// Percentage of the cells on every floor the player lit up
+#[derive(Resource, Default, PartialEq)]
+pub struct LightCoverage(pub f32);
+
+/// Light left behind in a cell the player visited
+#[derive(Component)]
+struct CellLight;
+
+// The run is won once the player stands on the exit of the top floor
+fn reach_exit(
+    player_query: Query<&Transform, With<Player>>,
+    maze: Res<Maze>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if maze.current_floor != maze.floors.len() - 1 {
+        return;
+    }
+    for transform in player_query.iter() {
+        if maze.cell_at(transform.translation.truncate()) == Some(maze.exit) {
+            next_state.set(GameState::Victory);
+        }
+    }
+}
+
+fn light_visited_cells(
+    mut commands: Commands,
+    player_query: Query<&Transform, With<Player>>,
+    mut node_query: Query<(Entity, &mut MazeNode)>,
+    maze: Res<Maze>,
+    color: Res<MazeColor>,
+) {
+    for transform in player_query.iter() {
+        let Some(cell) = maze.cell_at(transform.translation.truncate()) else {
+            continue;
+        };
+        for (entity, mut node) in node_query.iter_mut() {
+            if node.index != cell || node.lit[maze.current_floor] {
+                continue;
+            }
+            node.lit[maze.current_floor] = true;
+            commands.entity(entity).with_children(|parent| {
+                parent.spawn(cell_light(&maze, &color));
+            });
+        }
+    }
+}
+
+// Lit cells stay lit as long as an open path through other lit cells leads to
+// the player, so the origin-shift cutting them off puts them out again
+fn darken_disconnected_cells(
+    mut commands: Commands,
+    mut changes: EventReader<MazeEdgeChanged>,
+    player_query: Query<&Transform, With<Player>>,
+    mut node_query: Query<(Entity, &mut MazeNode)>,
+    light_query: Query<(Entity, &Parent), With<CellLight>>,
+    maze: Res<Maze>,
+) {
+    let closed = changes.read().any(|event| {
+        event.floor == maze.current_floor && matches!(event.change, EdgeChange::Closed(_))
+    });
+    if !closed {
+        return;
+    }
+    let Some(start) = player_query
+        .iter()
+        .find_map(|transform| maze.cell_at(transform.translation.truncate()))
+    else {
+        return;
+    };
+
+    let graph = maze.graph();
+    let mut lit = vec![false; graph.cell_count()];
+    for (_, node) in node_query.iter() {
+        lit[node.index] = node.lit[maze.current_floor];
+    }
+
+    let mut connected = vec![false; graph.cell_count()];
+    connected[start] = true;
+    let mut queue = VecDeque::from([start]);
+    while let Some(cell) = queue.pop_front() {
+        for next in graph.open_neighbours(cell) {
+            if lit[next] && !connected[next] {
+                connected[next] = true;
+                queue.push_back(next);
+            }
+        }
+    }
+
+    for (entity, mut node) in node_query.iter_mut() {
+        if !node.lit[maze.current_floor] || connected[node.index] {
+            continue;
+        }
+        node.lit[maze.current_floor] = false;
+        for (light, parent) in light_query.iter() {
+            if parent.get() == entity {
+                commands.entity(light).despawn_recursive();
+            }
+        }
+    }
+}
+
+// Only the lights of the current floor exist in the world
+fn spawn_cell_lights(
+    mut commands: Commands,
+    mut rebuilt: EventReader<MazeRebuilt>,
+    mut floor_changed: EventReader<MazeFloorChanged>,
+    node_query: Query<(Entity, &MazeNode)>,
+    light_query: Query<Entity, With<CellLight>>,
+    maze: Res<Maze>,
+    color: Res<MazeColor>,
+) {
+    if rebuilt.is_empty() && floor_changed.is_empty() {
+        return;
+    }
+    rebuilt.clear();
+    floor_changed.clear();
+
+    for entity in light_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for (entity, node) in node_query.iter() {
+        if node.lit[maze.current_floor] {
+            commands.entity(entity).with_children(|parent| {
+                parent.spawn(cell_light(&maze, &color));
+            });
+        }
+    }
+}
+
+fn update_coverage(
+    node_query: Query<&MazeNode>,
+    maze: Res<Maze>,
+    mut coverage: ResMut<LightCoverage>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let cells: usize = maze.floors.iter().map(|graph| graph.cells().count()).sum();
+    let lit: usize = node_query
+        .iter()
+        .map(|node| {
+            maze.floors
+                .iter()
+                .zip(&node.lit)
+                .filter(|(graph, lit)| **lit && graph.is_enabled(node.index))
+                .count()
+        })
+        .sum();
+
+    coverage.set_if_neq(LightCoverage(lit as f32 / cells.max(1) as f32 * 100.));
+    if lit == cells {
+        next_state.set(GameState::Victory);
+    }
+}
+
+fn cell_light(maze: &Maze, color: &MazeColor) -> impl Bundle {
+    (
+        PointLight2d {
+            intensity: 1.0,
+            radius: maze.cell_size,
+            falloff: 5.,
+            cast_shadows: true,
+            color: color.light_color,
+        },
+        Transform::default(),
+        CellLight,
+    )
+}
+
+// Leaving the results screen always starts in a fresh maze
+fn start_new_run(mut seed: ResMut<MazeSeed>) {
+    seed.0 = rand::random();
+}
@@ -4,7 +4,12 @@ use bevy::prelude::*;
 use bevy_light_2d::prelude::*;
 use bevy_rapier2d::prelude::*;
 
-use crate::maze::{Direction, Maze, MazeNode};
+use crate::{
+    gamestate::GameState,
+    maze::{Maze, MazeFloorChanged, MazeRebuilt},
+    maze_graph::Direction,
+    stealth::LightVisibility,
+};
 
 pub struct PlayerPlugin<S: States> {
     pub state: S,
@@ -13,31 +18,41 @@ pub struct PlayerPlugin<S: States> {
 impl<S: States> Plugin for PlayerPlugin<S> {
     fn build(&self, app: &mut App) {
         app.insert_resource(FirstRunTracker(false));
-        app.insert_resource(RangeNodes(Vec::new()));
         app.insert_resource(ManaState {
             mana_timer: Timer::from_seconds(0.0025, TimerMode::Repeating),
             recovery_timer: Timer::from_seconds(3.0, TimerMode::Once),
             percentage: 100.0,
             change_value: 0.1,
         });
+        app.add_event::<PlayerDamaged>();
         app.add_systems(OnEnter(self.state.clone()), spawn_player);
         app.add_systems(
             Update,
             (
                 update_player_state,
+                take_damage,
+                recover_from_hurt,
                 update_player,
                 update_player_animation,
                 animate_player_sprite,
-                glitch_wall,
-                update_range_nodes,
+                finish_death,
+                glitch_wall.run_if(player_alive),
+                push_out_of_walls,
+                take_stairs.run_if(player_alive),
             )
                 .chain()
                 .run_if(in_state(self.state.clone())),
         );
+        app.add_systems(Update, respawn_player);
     }
 }
 
 const PLAYER_FPS: u8 = 8;
+const PLAYER_HEALTH: u32 = 3;
+/// Seconds the player stays hurt, and can not be hurt again
+const HURT_DURATION: f32 = 1.0;
+/// Intensity of the light the player carries
+pub const PLAYER_LIGHT_INTENSITY: f32 = 20.0;
 
 #[derive(Component)]
 pub struct Player {
@@ -47,10 +62,23 @@ pub struct Player {
     against_wall: Vec<Direction>,
     state: PlayerState,
     direction: Direction,
+    hurt_timer: Timer,
+}
+
+#[derive(Component, Debug)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+/// Sent by anything that hurts the player
+#[derive(Event, Debug)]
+pub struct PlayerDamaged {
+    pub amount: u32,
 }
 
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
-enum PlayerState {
+pub enum PlayerState {
     Idle,
     Walking,
     Hurt,
@@ -63,6 +91,8 @@ struct PlayerAnimation {
     last_index: usize,
     frame_timer: Timer,
     flip_x: bool,
+    /// Whether the animation starts over after its last frame, or stops there
+    looping: bool,
 }
 
 impl PlayerAnimation {
@@ -72,9 +102,15 @@ impl PlayerAnimation {
             last_index,
             frame_timer: Self::timer_from_fps(PLAYER_FPS),
             flip_x,
+            looping: true,
         }
     }
 
+    fn once(mut self) -> Self {
+        self.looping = false;
+        self
+    }
+
     fn timer_from_fps(fps: u8) -> Timer {
         Timer::from_seconds(1. / fps as f32, TimerMode::Once)
     }
@@ -88,8 +124,9 @@ impl PartialEq for PlayerAnimation {
     }
 }
 
+/// Animations of the prototype character sheet, shared by the player and the NPCs
 #[derive(Component)]
-struct PlayerAnimations {
+pub struct PlayerAnimations {
     animations: HashMap<(PlayerState, Direction), PlayerAnimation>,
     current_animation: PlayerAnimation,
 }
@@ -148,19 +185,19 @@ impl PlayerAnimations {
         );
         animations.insert(
             (PlayerState::Death, Direction::Down),
-            PlayerAnimation::new(36, 38, false),
+            PlayerAnimation::new(36, 38, false).once(),
         );
         animations.insert(
             (PlayerState::Death, Direction::Left),
-            PlayerAnimation::new(40, 42, true),
+            PlayerAnimation::new(40, 42, true).once(),
         );
         animations.insert(
             (PlayerState::Death, Direction::Right),
-            PlayerAnimation::new(40, 42, false),
+            PlayerAnimation::new(40, 42, false).once(),
         );
         animations.insert(
             (PlayerState::Death, Direction::Up),
-            PlayerAnimation::new(44, 46, false),
+            PlayerAnimation::new(44, 46, false).once(),
         );
 
         let default_animation = animations[&(PlayerState::Idle, Direction::Up)].clone();
@@ -171,6 +208,27 @@ impl PlayerAnimations {
         }
     }
 
+    /// Switches to the animation for `state` and `dir`, restarting the sprite on a change
+    pub fn play(&mut self, state: PlayerState, dir: Direction, sprite: &mut Sprite) {
+        if self.update_animation(state, dir) {
+            if let Some(atlas) = &mut sprite.texture_atlas {
+                atlas.index = self.current_animation.first_index;
+            }
+        }
+        sprite.flip_x = self.current_animation.flip_x;
+    }
+
+    /// Whether an animation that plays once showed its last frame
+    fn is_finished(&self, sprite: &Sprite) -> bool {
+        let animation = &self.current_animation;
+        !animation.looping
+            && animation.frame_timer.finished()
+            && sprite
+                .texture_atlas
+                .as_ref()
+                .is_some_and(|atlas| atlas.index >= animation.last_index)
+    }
+
     fn update_animation(&mut self, state: PlayerState, dir: Direction) -> bool {
         if let Some(animation) = self.animations.get(&(state, dir)) {
             if &self.current_animation != animation {
@@ -196,17 +254,9 @@ pub struct ManaState {
     pub change_value: f32,
 }
 
-#[derive(Resource)]
-pub struct RangeNodes(pub Vec<Entity>);
-
 fn update_player_animation(mut query: Query<(&Player, &mut PlayerAnimations, &mut Sprite)>) {
     for (player, mut animations, mut sprite) in query.iter_mut() {
-        if animations.update_animation(player.state.clone(), player.direction) {
-            if let Some(atlas) = &mut sprite.texture_atlas {
-                atlas.index = animations.current_animation.first_index;
-            }
-        }
-        sprite.flip_x = animations.current_animation.flip_x;
+        animations.play(player.state.clone(), player.direction, &mut sprite);
     }
 }
 
@@ -217,6 +267,10 @@ fn animate_player_sprite(time: Res<Time>, mut query: Query<(&mut Sprite, &mut Pl
 
         if animation.frame_timer.just_finished() {
             if let Some(atlas) = &mut sprite.texture_atlas {
+                // animations that play once hold their last frame
+                if !animation.looping && atlas.index >= animation.last_index {
+                    continue;
+                }
                 atlas.index = if atlas.index >= animation.last_index {
                     animation.first_index
                 } else {
@@ -229,6 +283,36 @@ fn animate_player_sprite(time: Res<Time>, mut query: Query<(&mut Sprite, &mut Pl
     }
 }
 
+/// Sprite cut from a prototype character sheet, with the animations to go through it
+pub fn character_sprite(
+    path: &str,
+    asset_server: &AssetServer,
+    texture_atlases: &mut Assets<TextureAtlasLayout>,
+) -> (Sprite, PlayerAnimations) {
+    let image_handle: Handle<Image> = asset_server.load(path.to_string());
+    let layout = TextureAtlasLayout::from_grid(
+        UVec2::splat(16),
+        4,
+        12,
+        Some(UVec2::splat(16)),
+        Some(UVec2::splat(8)),
+    );
+    let texture_atlas_layout = texture_atlases.add(layout);
+
+    let animations = PlayerAnimations::new();
+    let sprite = Sprite {
+        image: image_handle,
+        texture_atlas: Some(TextureAtlas {
+            layout: texture_atlas_layout,
+            index: animations.current_animation.first_index,
+        }),
+        flip_x: animations.current_animation.flip_x,
+        ..default()
+    };
+
+    (sprite, animations)
+}
+
 fn spawn_player(
     mut commands: Commands,
     mut run_once: ResMut<FirstRunTracker>,
@@ -242,37 +326,23 @@ fn spawn_player(
         return;
     }
 
-    let image_handle: Handle<Image> =
-        asset_server.load("sprite/character/Prototype_Character_Blue.png");
-    let layout = TextureAtlasLayout::from_grid(
-        UVec2::splat(16),
-        4,
-        12,
-        Some(UVec2::splat(16)),
-        Some(UVec2::splat(8)),
+    let (sprite, player_animations) = character_sprite(
+        "sprite/character/Prototype_Character_Blue.png",
+        &asset_server,
+        &mut texture_atlases,
     );
-    let texture_atlas_layout = texture_atlases.add(layout);
-
-    let player_animations = PlayerAnimations::new();
 
     commands.spawn((
         PointLight2d {
-            intensity: 20.0,
+            intensity: PLAYER_LIGHT_INTENSITY,
             radius: maze.view_distance,
             falloff: 10.,
             cast_shadows: true,
             color: Color::WHITE,
         },
-        Sprite {
-            image: image_handle,
-            texture_atlas: Some(TextureAtlas {
-                layout: texture_atlas_layout,
-                index: player_animations.current_animation.first_index,
-            }),
-            flip_x: player_animations.current_animation.flip_x,
-            ..default()
-        },
+        sprite,
         player_animations,
+        Transform::from_translation(maze.cell_position(maze.spawn).extend(0.)),
         RigidBody::Dynamic,
         Velocity::default(),
         GravityScale(0.),
@@ -282,6 +352,13 @@ fn spawn_player(
         ActiveEvents::COLLISION_EVENTS,
         Ccd::enabled(),
         Collider::cuboid(8. * 0.5, 16. * 0.5),
+        (
+            LightVisibility::default(),
+            Health {
+                current: PLAYER_HEALTH,
+                max: PLAYER_HEALTH,
+            },
+        ),
         Player {
             speed: 200.0,
             sprint_factor: 1.5,
@@ -289,26 +366,11 @@ fn spawn_player(
             against_wall: Vec::new(),
             state: PlayerState::Idle,
             direction: Direction::Down,
+            hurt_timer: Timer::from_seconds(HURT_DURATION, TimerMode::Once),
         },
     ));
 }
 
-fn update_range_nodes(
-    player_pos: Query<&Transform, With<Player>>,
-    mut range_nodes: ResMut<RangeNodes>,
-    nodes: Query<(Entity, &Transform), With<MazeNode>>,
-    maze: Res<Maze>,
-) {
-    let player_pos = player_pos.single().translation.truncate();
-    let mut new_nodes = Vec::new();
-    for (node, transform) in nodes.iter() {
-        if transform.translation.distance(player_pos.extend(0.)) < 4.0 * maze.cell_size {
-            new_nodes.push(node);
-        }
-    }
-    range_nodes.0 = new_nodes;
-}
-
 fn update_player(
     keys: Res<ButtonInput<KeyCode>>,
     mut player_controllers: Query<(&mut Velocity, &mut Player)>,
@@ -317,6 +379,11 @@ fn update_player(
 ) {
     let (mut velocity, mut player) = player_controllers.single_mut();
 
+    if player.state == PlayerState::Death {
+        velocity.linvel = Vec2::ZERO;
+        return;
+    }
+
     let mut direction = Vec2::ZERO;
     if keys.pressed(KeyCode::KeyA) && !player.against_wall.contains(&Direction::Left) {
         direction.x -= 1.;
@@ -342,11 +409,16 @@ fn update_player(
         player.direction = Direction::Down;
     }
 
+    // the hurt animation plays out while the player keeps moving
     if direction != Vec2::ZERO {
         direction = direction.normalize();
-        player.state = PlayerState::Walking;
-    } else {
-        player.state = PlayerState::Idle;
+    }
+    if player.state != PlayerState::Hurt {
+        player.state = if direction != Vec2::ZERO {
+            PlayerState::Walking
+        } else {
+            PlayerState::Idle
+        };
     }
 
     // Shift for sprint
@@ -382,6 +454,55 @@ fn update_player(
     velocity.linvel = direction * speed;
 }
 
+// Damage only lands while the player is not already hurt, which gives them
+// a moment to get away
+fn take_damage(
+    mut damaged: EventReader<PlayerDamaged>,
+    mut player_query: Query<(&mut Player, &mut Health)>,
+) {
+    for event in damaged.read() {
+        for (mut player, mut health) in player_query.iter_mut() {
+            if matches!(player.state, PlayerState::Hurt | PlayerState::Death) {
+                continue;
+            }
+            health.current = health.current.saturating_sub(event.amount);
+            if health.current == 0 {
+                player.state = PlayerState::Death;
+            } else {
+                player.state = PlayerState::Hurt;
+                player.hurt_timer.reset();
+            }
+        }
+    }
+}
+
+fn recover_from_hurt(time: Res<Time>, mut player_query: Query<&mut Player>) {
+    for mut player in player_query.iter_mut() {
+        if player.state == PlayerState::Hurt && player.hurt_timer.tick(time.delta()).finished() {
+            player.state = PlayerState::Idle;
+        }
+    }
+}
+
+// The run is over once the death animation played out
+fn finish_death(
+    player_query: Query<(&Player, &PlayerAnimations, &Sprite)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (player, animations, sprite) in player_query.iter() {
+        if player.state == PlayerState::Death && animations.is_finished(sprite) {
+            next_state.set(GameState::GameOver);
+        }
+    }
+}
+
+/// Run condition for everything the player does, which stops once they are dying
+pub fn player_alive(player_query: Query<&Player>) -> bool {
+    player_query
+        .iter()
+        .all(|player| player.state != PlayerState::Death)
+}
+
 fn update_player_state(
     mut player_query: Query<(&mut Player, &Transform, &Collider)>,
     rapier_context: WriteRapierContext,
@@ -465,6 +586,7 @@ fn glitch_wall(
     keys: Res<ButtonInput<KeyCode>>,
     maze: Res<Maze>,
     mut mana_state: ResMut<ManaState>,
+    rapier_context: ReadRapierContext,
 ) {
     if !keys.just_pressed(KeyCode::KeyE) {
         return;
@@ -472,41 +594,126 @@ fn glitch_wall(
     for (player, mut transform) in player_query.iter_mut() {
         if mana_state.percentage >= 10.0 {
             for dir in player.against_wall.iter() {
-                match *dir {
-                    Direction::Left => {
-                        if !keys.pressed(KeyCode::KeyA) {
-                            continue;
-                        }
-                        transform.translation -= Vec3::new(maze.cell_size, 0., 0.);
-                        mana_state.percentage -= 10.0;
-                        mana_state.recovery_timer.reset();
-                    }
-                    Direction::Right => {
-                        if !keys.pressed(KeyCode::KeyD) {
-                            continue;
-                        }
-                        transform.translation += Vec3::new(maze.cell_size, 0., 0.);
-                        mana_state.percentage -= 10.0;
-                        mana_state.recovery_timer.reset();
-                    }
-                    Direction::Up => {
-                        if !keys.pressed(KeyCode::KeyW) {
-                            continue;
-                        }
-                        transform.translation += Vec3::new(0., maze.cell_size, 0.);
-                        mana_state.percentage -= 10.0;
-                        mana_state.recovery_timer.reset();
-                    }
-                    Direction::Down => {
-                        if !keys.pressed(KeyCode::KeyS) {
-                            continue;
-                        }
-                        transform.translation -= Vec3::new(0., maze.cell_size, 0.);
-                        mana_state.percentage -= 10.0;
-                        mana_state.recovery_timer.reset();
-                    }
+                let key = match *dir {
+                    Direction::Left => KeyCode::KeyA,
+                    Direction::Right => KeyCode::KeyD,
+                    Direction::Up => KeyCode::KeyW,
+                    Direction::Down => KeyCode::KeyS,
+                    _ => continue,
+                };
+                if !keys.pressed(key) {
+                    continue;
+                }
+                // land in the centre of the cell on the other side of the wall, whatever its shape
+                let position = transform.translation.truncate();
+                let target = position + maze.neighbour_offset(position, dir.vector());
+                let Some(cell) = maze
+                    .cell_at(target)
+                    .filter(|cell| maze.graph().is_enabled(*cell))
+                else {
+                    continue;
+                };
+                let landing = maze.cell_position(cell);
+                if !crosses_one_wall(&rapier_context, position, landing) {
+                    continue;
                 }
+
+                transform.translation = landing.extend(transform.translation.z);
+                mana_state.percentage -= 10.0;
+                mana_state.recovery_timer.reset();
+                break;
             }
         }
     }
 }
+
+// The wall the player leans on is the first one hit walking to the landing, and the
+// last one hit walking back, so a second wall in between shows up as two different hits
+fn crosses_one_wall(rapier_context: &ReadRapierContext, from: Vec2, to: Vec2) -> bool {
+    let rapier_context = rapier_context.single();
+    let filter = QueryFilter::exclude_dynamic().exclude_sensors();
+    let forward = rapier_context.cast_ray(from, to - from, 1., true, filter);
+    let backward = rapier_context.cast_ray(to, from - to, 1., true, filter);
+
+    matches!((forward, backward), (Some((first, _)), Some((last, _))) if first == last)
+}
+
+// Walls closing on the player or a landing gone wrong leave them inside a wall,
+// from where they get moved to the centre of the nearest cell
+fn push_out_of_walls(
+    mut player_query: Query<(&mut Transform, &Collider), With<Player>>,
+    maze: Res<Maze>,
+    rapier_context: ReadRapierContext,
+) {
+    for (mut transform, collider) in player_query.iter_mut() {
+        let position = transform.translation.truncate();
+        let current = maze
+            .cell_at(position)
+            .filter(|cell| maze.graph().is_enabled(*cell));
+        if current.is_some() && !overlaps_wall(&rapier_context, position, collider) {
+            continue;
+        }
+
+        // stay on the side of the wall the player is on, the other side would be a free glitch
+        if let Some(cell) = current.or_else(|| maze.nearest_cell(position)) {
+            let centre = maze.cell_position(cell);
+            transform.translation = centre.extend(transform.translation.z);
+        }
+    }
+}
+
+// Only the core of the collider counts, the physics already keep the player from brushing through walls
+fn overlaps_wall(rapier_context: &ReadRapierContext, position: Vec2, collider: &Collider) -> bool {
+    let half_extents = collider.as_cuboid().unwrap().half_extents();
+    let core = Collider::cuboid(half_extents.x * 0.5, half_extents.y * 0.5);
+
+    rapier_context
+        .single()
+        .intersection_with_shape(
+            position,
+            0.,
+            &core,
+            QueryFilter::<'_>::exclude_dynamic().exclude_sensors(),
+        )
+        .is_some()
+}
+
+fn take_stairs(
+    player_query: Query<&Transform, With<Player>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut maze: ResMut<Maze>,
+    mut floor_changed: EventWriter<MazeFloorChanged>,
+) {
+    if !keys.just_pressed(KeyCode::KeyF) {
+        return;
+    }
+    for transform in player_query.iter() {
+        let Some(cell) = maze.cell_at(transform.translation.truncate()) else {
+            continue;
+        };
+        if let Some(destination) = maze.stairs_destination(cell) {
+            maze.current_floor = destination;
+            floor_changed.send(MazeFloorChanged);
+        }
+    }
+}
+
+// A new maze starts the run over from its spawn
+fn respawn_player(
+    mut rebuilt: EventReader<MazeRebuilt>,
+    maze: Res<Maze>,
+    mut player_query: Query<(&mut Transform, &mut Player, &mut Health)>,
+) {
+    if rebuilt.is_empty() {
+        return;
+    }
+    rebuilt.clear();
+
+    for (mut transform, mut player, mut health) in player_query.iter_mut() {
+        transform.translation = maze
+            .cell_position(maze.spawn)
+            .extend(transform.translation.z);
+        player.state = PlayerState::Idle;
+        health.current = health.max;
+    }
+}
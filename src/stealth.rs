@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+use bevy_light_2d::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    maze::Maze,
+    player::{Player, PLAYER_LIGHT_INTENSITY},
+};
+
+pub struct StealthPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for StealthPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerLight>();
+        app.add_systems(
+            Update,
+            (toggle_player_light, update_light_visibility)
+                .chain()
+                .run_if(in_state(self.state.clone())),
+        );
+    }
+}
+
+/// Light intensity at which an entity counts as fully visible, twice the light of
+/// the player so the player needs another light nearby to be seen clearly
+const FULL_LIGHT: f32 = PLAYER_LIGHT_INTENSITY * 2.;
+/// Share of its intensity the light of the player keeps while dimmed
+const DIMMED_INTENSITY: f32 = 0.25;
+/// Share of the view distance the light of the player reaches while dimmed
+const DIMMED_VIEW_DISTANCE: f32 = 0.5;
+
+/// How well an entity can be seen, from 0 in the dark to 1 in full light
+#[derive(Component, Default)]
+pub struct LightVisibility(pub f32);
+
+/// Whether the player dimmed their light, which shortens how far they see
+#[derive(Resource, Default)]
+pub struct PlayerLight {
+    dimmed: bool,
+}
+
+impl PlayerLight {
+    /// How far the player sees, `Maze::view_distance` unless the light is dimmed
+    pub fn view_distance(&self, maze: &Maze) -> f32 {
+        if self.dimmed {
+            maze.view_distance * DIMMED_VIEW_DISTANCE
+        } else {
+            maze.view_distance
+        }
+    }
+}
+
+// Press V to dim the light of the player, hiding them better but seeing less of the maze.
+// Lamps keep the full view distance.
+fn toggle_player_light(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut player_light: ResMut<PlayerLight>,
+    mut light_query: Query<(&mut PointLight2d, Ref<Player>)>,
+    maze: Res<Maze>,
+) {
+    if keys.just_pressed(KeyCode::KeyV) {
+        player_light.dimmed = !player_light.dimmed;
+    }
+
+    let intensity = if player_light.dimmed {
+        DIMMED_INTENSITY
+    } else {
+        1.
+    };
+    for (mut light, player) in light_query.iter_mut() {
+        if player_light.is_changed() || player.is_added() {
+            light.intensity = PLAYER_LIGHT_INTENSITY * intensity;
+            light.radius = player_light.view_distance(&maze);
+        }
+    }
+}
+
+// Every light that reaches an entity adds to its visibility, fading out toward
+// the edge of the light, unless a wall casts a shadow over it. An entity is lit
+// by its own light as well.
+fn update_light_visibility(
+    rapier_context: ReadRapierContext,
+    light_query: Query<(&PointLight2d, &GlobalTransform)>,
+    mut visibility_query: Query<(&GlobalTransform, &mut LightVisibility)>,
+) {
+    let rapier_context = rapier_context.single();
+
+    for (transform, mut visibility) in visibility_query.iter_mut() {
+        let position = transform.translation().truncate();
+        let light: f32 = light_query
+            .iter()
+            .filter_map(|(light, light_transform)| {
+                let source = light_transform.translation().truncate();
+                let distance = source.distance(position);
+                if distance >= light.radius {
+                    return None;
+                }
+                let in_shadow = light.cast_shadows
+                    && distance > 0.
+                    && rapier_context
+                        .cast_ray(
+                            position,
+                            source - position,
+                            1.,
+                            true,
+                            QueryFilter::exclude_dynamic().exclude_sensors(),
+                        )
+                        .is_some();
+                (!in_shadow).then(|| light.intensity * (1. - distance / light.radius))
+            })
+            .sum();
+
+        visibility.0 = (light / FULL_LIGHT).min(1.);
+    }
+}
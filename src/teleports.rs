@@ -0,0 +1,318 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    maze::{Maze, MazeFloorChanged, MazeNode, MazeRebuilt},
+    maze_specs::MazeColor,
+    menu_screens::{HOVERED_BUTTON_COLOR, NORMAL_BUTTON_COLOR, PRESSED_BUTTON_COLOR},
+    player::{player_alive, ManaState, Player},
+};
+
+pub struct TeleportPlugin<S: States> {
+    pub state: S,
+}
+
+impl<S: States> Plugin for TeleportPlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Stations>();
+        app.add_systems(Update, spawn_station_markers);
+        app.add_systems(
+            Update,
+            (use_station, close_teleport_menu, teleport_button_system)
+                .chain()
+                .before(spawn_station_markers)
+                .run_if(in_state(self.state.clone()))
+                .run_if(player_alive),
+        );
+        app.add_systems(OnExit(self.state.clone()), despawn_teleport_menu);
+    }
+}
+
+const BUILD_COST: f32 = 25.0;
+const TRAVEL_COST_PER_STEP: f32 = 0.5;
+
+/// Teleport stations the player built, cleared with every new maze
+#[derive(Resource, Default)]
+struct Stations(Vec<Station>);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Station {
+    floor: usize,
+    cell: usize,
+}
+
+/// Marks the cell of a station on the current floor
+#[derive(Component)]
+struct StationMarker;
+
+/// List of stations to travel to, open while the player stands on a station
+#[derive(Component)]
+struct TeleportMenu;
+
+#[derive(Component)]
+struct TeleportButton(Station);
+
+// Press T to build a station in the current cell, or to pick a destination when standing on one
+#[allow(clippy::too_many_arguments)]
+fn use_station(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    player_query: Query<&Transform, With<Player>>,
+    menu_query: Query<Entity, With<TeleportMenu>>,
+    maze: Res<Maze>,
+    mut stations: ResMut<Stations>,
+    mut mana_state: ResMut<ManaState>,
+) {
+    if !keys.just_pressed(KeyCode::KeyT) {
+        return;
+    }
+    let Some(cell) = player_query
+        .iter()
+        .find_map(|transform| maze.cell_at(transform.translation.truncate()))
+    else {
+        return;
+    };
+    let here = Station {
+        floor: maze.current_floor,
+        cell,
+    };
+
+    if !stations.0.contains(&here) {
+        if mana_state.percentage >= BUILD_COST {
+            stations.0.push(here);
+            mana_state.percentage -= BUILD_COST;
+            mana_state.recovery_timer.reset();
+        }
+        return;
+    }
+
+    if !menu_query.is_empty() {
+        for entity in menu_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+
+    let font = asset_server.load("fonts/MatrixtypeDisplay-9MyE5.ttf");
+    let distances = maze.walking_distances(cell);
+    let destinations: Vec<(Station, String)> = stations
+        .0
+        .iter()
+        .filter(|station| **station != here)
+        .map(|station| {
+            let (x, y) = maze.graph().coords(station.cell);
+            let cost = match travel_cost(&distances, *station) {
+                Some(cost) => format!("{:.0}", cost),
+                None => "walled off".to_string(),
+            };
+            (
+                *station,
+                format!("Floor {} ({}, {}): {}", station.floor + 1, x, y, cost),
+            )
+        })
+        .collect();
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Auto,
+                height: Val::Auto,
+                align_self: AlignSelf::FlexEnd,
+                justify_self: JustifySelf::Center,
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(5.)),
+                row_gap: Val::Px(5.0),
+                margin: UiRect::bottom(Val::Vh(2.)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.05)),
+            BorderRadius::all(Val::Px(10.0)),
+            TeleportMenu,
+        ))
+        .with_children(|parent| {
+            if destinations.is_empty() {
+                parent.spawn((
+                    Text::new("No other stations"),
+                    TextFont {
+                        font: font.clone(),
+                        font_size: 15.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                ));
+            }
+            for (station, label) in destinations {
+                parent
+                    .spawn((
+                        Button,
+                        Node {
+                            width: Val::Auto,
+                            height: Val::Px(30.),
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::Center,
+                            padding: UiRect::all(Val::Px(5.)),
+                            ..default()
+                        },
+                        BackgroundColor(NORMAL_BUTTON_COLOR),
+                        BorderRadius::MAX,
+                        TeleportButton(station),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            Text::new(label),
+                            TextFont {
+                                font: font.clone(),
+                                font_size: 15.0,
+                                ..default()
+                            },
+                            TextColor(Color::srgba(1.0, 1.0, 1.0, 1.0)),
+                        ));
+                    });
+            }
+        });
+}
+
+// Walking away from the station closes its menu
+fn close_teleport_menu(
+    mut commands: Commands,
+    player_query: Query<&Transform, With<Player>>,
+    menu_query: Query<Entity, With<TeleportMenu>>,
+    maze: Res<Maze>,
+    stations: Res<Stations>,
+) {
+    let on_station = player_query.iter().any(|transform| {
+        maze.cell_at(transform.translation.truncate())
+            .is_some_and(|cell| {
+                stations.0.contains(&Station {
+                    floor: maze.current_floor,
+                    cell,
+                })
+            })
+    });
+    if on_station {
+        return;
+    }
+    for entity in menu_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn teleport_button_system(
+    mut commands: Commands,
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &TeleportButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut player_query: Query<(&mut Transform, &mut Velocity), With<Player>>,
+    menu_query: Query<Entity, With<TeleportMenu>>,
+    mut maze: ResMut<Maze>,
+    mut mana_state: ResMut<ManaState>,
+    mut floor_changed: EventWriter<MazeFloorChanged>,
+) {
+    for (interaction, mut bg_color, destination) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *bg_color = BackgroundColor(PRESSED_BUTTON_COLOR);
+                let destination = destination.0;
+
+                for (mut transform, mut velocity) in player_query.iter_mut() {
+                    let Some(cell) = maze.cell_at(transform.translation.truncate()) else {
+                        continue;
+                    };
+                    // the maze kept shifting while the menu was open
+                    let distances = maze.walking_distances(cell);
+                    let Some(cost) = travel_cost(&distances, destination) else {
+                        continue;
+                    };
+                    if mana_state.percentage < cost {
+                        continue;
+                    }
+
+                    mana_state.percentage -= cost;
+                    mana_state.recovery_timer.reset();
+                    if destination.floor != maze.current_floor {
+                        maze.current_floor = destination.floor;
+                        floor_changed.send(MazeFloorChanged);
+                    }
+                    let position = maze.cell_position(destination.cell);
+                    transform.translation = position.extend(transform.translation.z);
+                    velocity.linvel = Vec2::ZERO;
+
+                    for entity in menu_query.iter() {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *bg_color = BackgroundColor(HOVERED_BUTTON_COLOR);
+            }
+            Interaction::None => {
+                *bg_color = BackgroundColor(NORMAL_BUTTON_COLOR);
+            }
+        }
+    }
+}
+
+fn despawn_teleport_menu(mut commands: Commands, menu_query: Query<Entity, With<TeleportMenu>>) {
+    for entity in menu_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Only the stations of the current floor exist in the world
+#[allow(clippy::too_many_arguments)]
+fn spawn_station_markers(
+    mut commands: Commands,
+    mut rebuilt: EventReader<MazeRebuilt>,
+    mut floor_changed: EventReader<MazeFloorChanged>,
+    node_query: Query<(Entity, &MazeNode)>,
+    marker_query: Query<Entity, With<StationMarker>>,
+    maze: Res<Maze>,
+    mut stations: ResMut<Stations>,
+    color: Res<MazeColor>,
+) {
+    if rebuilt.is_empty() && floor_changed.is_empty() && !stations.is_changed() {
+        return;
+    }
+    if !rebuilt.is_empty() {
+        stations.0.clear();
+    }
+    rebuilt.clear();
+    floor_changed.clear();
+
+    for entity in marker_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for (entity, node) in node_query.iter() {
+        let here = Station {
+            floor: maze.current_floor,
+            cell: node.index,
+        };
+        if !stations.0.contains(&here) {
+            continue;
+        }
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn((
+                ShapeBundle {
+                    path: GeometryBuilder::build_as(&shapes::RegularPolygon {
+                        sides: 4,
+                        feature: shapes::RegularPolygonFeature::Radius(maze.path_thickness * 0.3),
+                        ..default()
+                    }),
+                    transform: Transform::from_translation(Vec3::new(0., 0., -6.)),
+                    ..default()
+                },
+                Fill::color(color.station_color),
+                StationMarker,
+            ));
+        });
+    }
+}
+
+/// Mana it takes to reach a station, `None` once the walls cut it off
+fn travel_cost(distances: &[Vec<Option<usize>>], station: Station) -> Option<f32> {
+    distances[station.floor][station.cell].map(|distance| distance as f32 * TRAVEL_COST_PER_STEP)
+}
@@ -1,3 +1,5 @@
+use std::collections::{HashMap, HashSet};
+
 use bevy::prelude::*;
 use bevy_light_2d::prelude::*;
 use bevy_prototype_lyon::prelude::*;
@@ -5,9 +7,11 @@ use bevy_rapier2d::prelude::*;
 
 use crate::{
     gamestate::GameState,
-    maze::{Maze, MazeNode},
+    maze::{Maze, MazeEdgeChanged, MazeFloorChanged, MazeNode, MazeRebuilt, VisibleEdges},
+    maze_graph::{Edge, EdgeChange},
     maze_specs::MazeColor,
-    player::RangeNodes,
+    player::Player,
+    stealth::PlayerLight,
 };
 
 pub struct WallPlugin<S: States> {
@@ -16,163 +20,363 @@ pub struct WallPlugin<S: States> {
 
 impl<S: States> Plugin for WallPlugin<S> {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_walls);
+        app.init_resource::<WallRegistry>();
+        app.init_resource::<SolidWalls>();
+        app.add_systems(Update, (spawn_walls, update_solid_walls).chain());
+        app.add_systems(
+            Update,
+            toggle_walls
+                .after(update_solid_walls)
+                .run_if(in_state(self.state.clone())),
+        );
         app.add_systems(
             Update,
-            (spawn_colliders, remove_colliders)
-                .chain()
+            update_visible_edges
+                .after(toggle_walls)
                 .run_if(in_state(self.state.clone())),
         );
     }
 }
 
+/// Persistent wall on a maze edge, only carrying colliders while the edge is closed
 #[derive(Component)]
 pub struct Wall;
 
-fn setup_walls(maze: Res<Maze>, mut commands: Commands, color: Res<MazeColor>) {
+/// Filler for cells the maze mask disabled
+#[derive(Component)]
+struct MaskedCell;
+
+/// Path coloured area behind the maze
+#[derive(Component)]
+struct MazeBackground;
+
+/// Marks a cell with stairs to another floor
+#[derive(Component)]
+struct StairsMarker;
+
+/// Marks the cell that ends the run, only on the top floor
+#[derive(Component)]
+struct ExitMarker;
+
+/// Wall entity of every maze edge, so opening or closing a passage is a direct lookup
+#[derive(Resource, Default)]
+pub struct WallRegistry(pub HashMap<Edge, Entity>);
+
+/// Edges whose walls carry colliders and occluders while closed: those around the
+/// player, or all of them while scanning
+#[derive(Resource, Default)]
+struct SolidWalls(HashSet<Edge>);
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn spawn_walls(
+    mut commands: Commands,
+    mut rebuilt: EventReader<MazeRebuilt>,
+    mut floor_changed: EventReader<MazeFloorChanged>,
+    maze: Res<Maze>,
+    mut registry: ResMut<WallRegistry>,
+    mut solid_walls: ResMut<SolidWalls>,
+    node_query: Query<(Entity, &MazeNode)>,
+    fillers: Query<
+        Entity,
+        Or<(
+            With<MaskedCell>,
+            With<MazeBackground>,
+            With<StairsMarker>,
+            With<ExitMarker>,
+        )>,
+    >,
+    color: Res<MazeColor>,
+) {
+    // only the current floor has walls, so they get replaced when the player changes floors
+    if rebuilt.is_empty() && floor_changed.is_empty() {
+        return;
+    }
+    rebuilt.clear();
+    floor_changed.clear();
+
+    for (_, entity) in registry.0.drain() {
+        commands.entity(entity).despawn_recursive();
+    }
+    // the new walls start out bare, they get solid once the player is near them
+    solid_walls.0.clear();
+    for entity in fillers.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
     // Background
+    let bounds = maze
+        .bounds()
+        .inflate((maze.cell_size - maze.path_thickness) * 0.5);
     commands.spawn((
         ShapeBundle {
             path: GeometryBuilder::build_as(&shapes::Rectangle {
-                extents: Vec2::new(
-                    maze.grid[0].len() as f32 * maze.cell_size
-                        + (maze.cell_size - maze.path_thickness),
-                    maze.grid.len() as f32 * maze.cell_size
-                        + (maze.cell_size - maze.path_thickness),
-                ),
+                extents: bounds.size(),
                 ..default()
             }),
-            transform: Transform::from_translation(Vec3::new(0., 0., -10.)),
+            transform: Transform::from_translation(bounds.center().extend(-10.)),
             ..default()
         },
         Fill::color(color.path_color),
+        MazeBackground,
     ));
 
-    // Borders
-    let directions = [
-        Vec2::new(1., 0.),
-        Vec2::new(0., 1.),
-        Vec2::new(-1., 0.),
-        Vec2::new(0., -1.),
-    ];
-    for direction in directions.iter() {
-        let shape = shapes::Rectangle {
-            extents: Vec2::new(
-                maze.grid[0].len() as f32 * maze.cell_size * direction.x.abs()
-                    + (maze.cell_size - maze.path_thickness),
-                maze.grid.len() as f32 * maze.cell_size * direction.y.abs()
-                    + (maze.cell_size - maze.path_thickness),
-            ),
-            ..default()
-        };
+    // Every node owns the walls towards its neighbours with a higher index and
+    // the permanent walls towards the border or disabled cells
+    for (node_entity, node) in node_query.iter() {
+        let (x, y) = maze.graph().coords(node.index);
 
-        commands.spawn((
-            Transform::from_translation(Vec3::new(
-                maze.grid[0].len() as f32 * maze.cell_size * direction.y * 0.5,
-                maze.grid.len() as f32 * maze.cell_size * direction.x * 0.5,
-                0.,
-            )),
-            Collider::cuboid(shape.extents.x * 0.5, shape.extents.y * 0.5),
-            LightOccluder2d {
-                shape: LightOccluder2dShape::Rectangle {
-                    half_size: Vec2::new(shape.extents.x * 0.5, shape.extents.y * 0.5),
-                },
-            },
-        ));
+        if !maze.graph().is_enabled(node.index) {
+            let corners = maze
+                .graph()
+                .topology()
+                .corners(IVec2::new(x as i32, y as i32), maze.cell_size);
+            commands
+                .spawn((
+                    ShapeBundle {
+                        path: GeometryBuilder::build_as(&shapes::Polygon {
+                            points: corners,
+                            closed: true,
+                        }),
+                        transform: Transform::from_translation(Vec3::new(0., 0., -5.)),
+                        ..default()
+                    },
+                    Fill::color(color.wall_color),
+                    MaskedCell,
+                ))
+                .set_parent(node_entity);
+            continue;
+        }
+
+        if let Some(destination) = maze.stairs_destination(node.index) {
+            // pointing up or down depending on where the stairs lead
+            let angle = if destination > maze.current_floor {
+                0.
+            } else {
+                std::f32::consts::PI
+            };
+            commands
+                .spawn((
+                    ShapeBundle {
+                        path: GeometryBuilder::build_as(&shapes::RegularPolygon {
+                            sides: 3,
+                            feature: shapes::RegularPolygonFeature::Radius(
+                                maze.path_thickness * 0.3,
+                            ),
+                            ..default()
+                        }),
+                        transform: Transform::from_translation(Vec3::new(0., 0., -5.))
+                            .with_rotation(Quat::from_rotation_z(angle)),
+                        ..default()
+                    },
+                    Fill::color(color.wall_color),
+                    StairsMarker,
+                ))
+                .set_parent(node_entity);
+        }
+
+        if node.index == maze.exit && maze.current_floor == maze.floors.len() - 1 {
+            // glows faintly so it can be spotted from the edge of the view distance
+            commands
+                .spawn((
+                    ShapeBundle {
+                        path: GeometryBuilder::build_as(&shapes::Circle {
+                            radius: maze.path_thickness * 0.3,
+                            ..default()
+                        }),
+                        transform: Transform::from_translation(Vec3::new(0., 0., -5.)),
+                        ..default()
+                    },
+                    Fill::color(color.exit_color),
+                    PointLight2d {
+                        intensity: 2.0,
+                        radius: maze.cell_size,
+                        falloff: 5.,
+                        cast_shadows: true,
+                        color: color.exit_color,
+                    },
+                    ExitMarker,
+                ))
+                .set_parent(node_entity);
+        }
+
+        for &direction in maze.graph().directions(node.index) {
+            let neighbour = maze.graph().neighbour(node.index, direction);
+            if neighbour.is_some_and(|neighbour| neighbour < node.index) {
+                continue;
+            }
+
+            let edge = Edge {
+                cell: node.index,
+                direction,
+            };
+            let (midpoint, _) = maze.edge_segment(edge);
+            let offset = midpoint - node.position;
+
+            let wall = commands
+                .spawn((Transform::from_translation(offset.extend(0.)), Wall))
+                .set_parent(node_entity)
+                .id();
+            registry.0.insert(edge, wall);
+        }
     }
 }
 
-fn spawn_colliders(
-    wall_colliders: Query<Entity, With<Wall>>,
-    maze: Res<Maze>,
+// Only walls within a cell of the view distance collide and cast shadows, so big
+// mazes do not fill the physics world. A scan lights up the whole maze, which
+// needs the shadows of every wall.
+fn update_solid_walls(
     mut commands: Commands,
-    node_query: Query<(&MazeNode, Entity)>,
-    range_nodes: Res<RangeNodes>,
+    player_query: Query<&Transform, With<Player>>,
+    maze: Res<Maze>,
+    registry: Res<WallRegistry>,
+    mut solid_walls: ResMut<SolidWalls>,
     game_state: Res<State<GameState>>,
 ) {
-    for entity in wall_colliders.iter() {
-        commands.entity(entity).despawn();
-    }
+    let position = player_query
+        .get_single()
+        .ok()
+        .map(|player| player.translation.truncate());
+    let scanning = game_state.get() == &GameState::Scanning;
+    let range = maze.view_distance + maze.cell_size;
 
-    // Spawn colliders for all the walls
-    for (node, node_entity) in node_query.iter() {
-        //Check if the node is inside the range_nodes
-        if !range_nodes.0.contains(&node_entity) && game_state.get() != &GameState::Scanning {
+    for (&edge, &entity) in registry.0.iter() {
+        let solid = scanning
+            || position
+                .is_some_and(|position| maze.edge_segment(edge).0.distance(position) <= range);
+        if solid == solid_walls.0.contains(&edge) {
             continue;
         }
 
-        let mut directions = Vec::new();
-
-        if node.index.x > 0. {
-            directions.push(Vec2::new(-1., 0.));
-        }
-        if node.index.x < maze.grid[0].len() as f32 - 1. {
-            directions.push(Vec2::new(1., 0.));
+        if solid {
+            solid_walls.0.insert(edge);
+            if !maze.graph().is_open(edge) {
+                close_wall(&mut commands.entity(entity), &maze, edge);
+            }
+        } else {
+            solid_walls.0.remove(&edge);
+            open_wall(&mut commands.entity(entity));
         }
-        if node.index.y > 0. {
-            directions.push(Vec2::new(0., -1.));
+    }
+}
+
+fn toggle_walls(
+    mut commands: Commands,
+    mut edge_changed: EventReader<MazeEdgeChanged>,
+    maze: Res<Maze>,
+    registry: Res<WallRegistry>,
+    solid_walls: Res<SolidWalls>,
+) {
+    for MazeEdgeChanged { floor, change } in edge_changed.read() {
+        if *floor != maze.current_floor {
+            continue;
         }
-        if node.index.y < maze.grid.len() as f32 - 1. {
-            directions.push(Vec2::new(0., 1.));
+
+        let (EdgeChange::Opened(edge) | EdgeChange::Closed(edge)) = *change;
+        let Some(&entity) = registry.0.get(&edge) else {
+            continue;
+        };
+        // walls out of range stay bare, they get closed once the player gets near
+        if !solid_walls.0.contains(&edge) {
+            continue;
         }
 
-        directions.iter().for_each(|direction| {
-            let shape = Vec2::new(
-                (maze.cell_size - maze.path_thickness) * direction.x
-                    + (2. * maze.cell_size - maze.path_thickness) * direction.y,
-                (maze.cell_size - maze.path_thickness) * direction.y
-                    + (2. * maze.cell_size - maze.path_thickness) * direction.x,
-            );
-
-            commands.spawn((
-                Collider::cuboid(shape.x * 0.5, shape.y * 0.5),
-                Transform::from_translation(
-                    node.position.extend(0.)
-                        + Vec3::new(
-                            maze.cell_size * direction.x * 0.5,
-                            maze.cell_size * direction.y * 0.5,
-                            0.,
-                        ),
-                ),
-                LightOccluder2d {
-                    shape: LightOccluder2dShape::Rectangle {
-                        half_size: shape * 0.5,
-                    },
-                },
-                Wall,
-            ));
-        });
+        match change {
+            EdgeChange::Opened(_) => open_wall(&mut commands.entity(entity)),
+            EdgeChange::Closed(_) => {
+                close_wall(&mut commands.entity(entity), &maze, edge);
+            }
+        }
     }
 }
 
-fn remove_colliders(
-    mut commands: Commands,
-    wall_colliders: Query<(Entity, &Transform), With<Wall>>,
+// An edge is in sight when a ray from the player reaches one of a few points
+// along it without hitting another wall first. Anything beyond the light is dark,
+// so only edges within the view distance of the player are checked.
+fn update_visible_edges(
+    rapier_context: ReadRapierContext,
+    player_query: Query<&Transform, With<Player>>,
     maze: Res<Maze>,
-    node_query: Query<&MazeNode>,
+    player_light: Res<PlayerLight>,
+    registry: Res<WallRegistry>,
+    mut visible_edges: ResMut<VisibleEdges>,
 ) {
-    for node in node_query.iter() {
-        if node.parent.is_none() {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let rapier_context = rapier_context.single();
+    let position = player.translation.truncate();
+    let side = maze.graph().topology().side_length(maze.cell_size);
+
+    visible_edges.0.clear();
+    for (&edge, &wall) in registry.0.iter() {
+        let (midpoint, along) = maze.edge_segment(edge);
+        if midpoint.distance(position) > player_light.view_distance(&maze) + side {
             continue;
         }
-        let parent_node = node_query.get(node.parent.unwrap()).unwrap();
-        let parent_direction = parent_node.index - node.index;
 
-        let node_translation = node.position.extend(0.);
+        let in_sight = [-0.4, 0., 0.4].iter().any(|offset| {
+            let target = midpoint + along * side * offset;
+            rapier_context
+                .cast_ray(
+                    position,
+                    target - position,
+                    1.,
+                    true,
+                    QueryFilter::exclude_dynamic().exclude_sensors(),
+                )
+                .is_none_or(|(entity, _)| entity == wall)
+        });
+        if in_sight {
+            visible_edges.0.insert(edge);
+        }
+    }
+}
 
-        let offset = Vec2::new(
-            parent_direction.x * maze.cell_size * 0.5,
-            parent_direction.y * maze.cell_size * 0.5,
-        );
+/// Takes the collider and light occluders off a wall
+fn open_wall(wall: &mut EntityCommands) {
+    wall.remove::<Collider>().despawn_descendants();
+}
 
-        for (entity, transform) in wall_colliders.iter() {
-            if transform
-                .translation
-                .distance(node_translation + Vec3::new(offset.x, offset.y, 0.))
-                < 0.1
-            {
-                commands.entity(entity).despawn();
-            }
+/// Gives a wall its collider and light occluders.
+///
+/// Occluders can not be rotated, so walls that are not axis-aligned get a row
+/// of small square occluders along their length instead of a single one.
+fn close_wall(wall: &mut EntityCommands, maze: &Maze, edge: Edge) {
+    let (_, along) = maze.edge_segment(edge);
+    let thickness = maze.cell_size - maze.path_thickness;
+    let length = maze.graph().topology().side_length(maze.cell_size) + thickness;
+
+    wall.insert(Collider::compound(vec![(
+        Vec2::ZERO,
+        along.to_angle(),
+        Collider::cuboid(length * 0.5, thickness * 0.5),
+    )]));
+
+    // closing twice must not stack occluders
+    wall.despawn_descendants();
+    wall.with_children(|parent| {
+        if along.x.abs() < 0.001 || along.y.abs() < 0.001 {
+            let half_size = (along.abs() * length + along.perp().abs() * thickness) * 0.5;
+            parent.spawn((
+                Transform::default(),
+                LightOccluder2d {
+                    shape: LightOccluder2dShape::Rectangle { half_size },
+                },
+            ));
+            return;
         }
-    }
+
+        let count = (length / thickness).ceil() as usize;
+        for step in 0..count {
+            let distance = (step as f32 + 0.5) / count as f32 * length - length * 0.5;
+            parent.spawn((
+                Transform::from_translation((along * distance).extend(0.)),
+                LightOccluder2d {
+                    shape: LightOccluder2dShape::Rectangle {
+                        half_size: Vec2::splat(thickness * 0.5),
+                    },
+                },
+            ));
+        }
+    });
 }